@@ -0,0 +1,66 @@
+// Command-line arguments for `tw`.
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use imap::types::Uid;
+
+/// Files links sent to the TMBU mailbox as posts in a Zola site.
+#[derive(Parser)]
+#[command(version)]
+pub(crate) struct Cli {
+    /// Read settings from this file instead of `~/.config/tw/config.toml`.
+    #[arg(long, env = "TW_CONFIG", global = true)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Accept the computed title and tags for each post without asking,
+    /// except for posts that trip one of the `review_when` rules.
+    #[arg(long, short, global = true)]
+    pub(crate) yes: bool,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// File every pending message as a post (the default).
+    Run,
+
+    /// Parse and enrich every pending message and show the posts that would
+    /// be filed, without writing files, committing, or touching the mail.
+    DryRun,
+
+    /// Show the post that a single message would become.
+    Preview {
+        /// UID of the message.
+        uid: Uid,
+
+        /// Mailbox containing the message. Defaults to the first configured
+        /// mailbox.
+        #[arg(long)]
+        mailbox: Option<String>,
+    },
+
+    /// List pending messages.
+    List,
+
+    /// File posts from a directory of `.eml` files or an mbox archive instead
+    /// of the mailbox.
+    Import {
+        /// Directory of `.eml` files, mbox archive, or single message.
+        path: PathBuf,
+
+        /// Add this tag to every imported post. May be repeated.
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Show the posts that would be filed without writing or committing
+        /// them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Keep running, filing posts as messages arrive.
+    Daemon,
+}
@@ -0,0 +1,617 @@
+use std::{
+    collections::HashSet,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    connection::Security,
+    error::{Error, Result},
+    filter::SenderFilter,
+    oauth2::OAuth2,
+    processed::ProcessedAction,
+    quarantine::Quarantine,
+    review::ReviewRule,
+};
+
+/// Settings for a run.
+///
+/// These are read from a TOML file (`~/.config/tw/config.toml` unless
+/// `--config` or `TW_CONFIG` says otherwise). Any of them can be overridden by
+/// the corresponding `TMBU_*` environment variable.
+pub(crate) struct Config {
+    // Resolved by `imap()`, since only some commands connect.
+    imap: ImapSection,
+    oauth2: OAuth2Section,
+    pub(crate) zola_root: PathBuf,
+
+    /// Canonical tag spellings. Defaults to `tags.toml` in the Zola root.
+    pub(crate) tag_dictionary: PathBuf,
+    pub(crate) mailboxes: Vec<SourceMailbox>,
+    pub(crate) search: String,
+    pub(crate) batch_size: usize,
+    pub(crate) sender_filter: Option<SenderFilter>,
+    pub(crate) quarantine: Option<Quarantine>,
+    pub(crate) processed_action: ProcessedAction,
+
+    /// Where messages that I defer during review are moved.
+    pub(crate) later_mailbox: String,
+
+    /// If false, posts are filed as-is without asking for confirmation.
+    pub(crate) interactive: bool,
+
+    /// If true, posts are shown but not written or committed, and messages
+    /// are left as they are.
+    pub(crate) dry_run: bool,
+
+    /// If true (and `interactive` is also true), only ask for confirmation
+    /// of posts that trip one of the `review_rules`.
+    pub(crate) batch: bool,
+    pub(crate) review_rules: Vec<ReviewRule>,
+}
+
+/// How to connect and log in to the IMAP server.
+pub(crate) struct ImapConfig {
+    pub(crate) host: String,
+    pub(crate) port: Option<u16>,
+    pub(crate) security: Security,
+    pub(crate) ca_file: Option<PathBuf>,
+    pub(crate) accept_invalid_certs_for: Option<String>,
+    pub(crate) username: String,
+    pub(crate) auth: Auth,
+}
+
+pub(crate) enum Auth {
+    Password(String),
+    XOAuth2(OAuth2),
+}
+
+/// A mailbox to read posts from, along with tags to add to every post that
+/// comes from it.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SourceMailbox {
+    pub(crate) name: String,
+
+    #[serde(default)]
+    pub(crate) tags: HashSet<String>,
+}
+
+impl Config {
+    /// Reads and validates the configuration. If anything required is
+    /// missing or invalid, the error lists all of the problems, not just the
+    /// first one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut file = match path {
+            Some(path) => ConfigFile::read(path, false)?,
+            None => ConfigFile::read(&config_dir().join("config.toml"), true)?,
+        };
+
+        let mut problems = Problems::default();
+        file.apply_env(&mut problems);
+
+        let zola_root = problems.require(file.zola_root, "zola_root", "TMBU_ZOLA_ROOT");
+
+        let mailboxes = if file.mailboxes.is_empty() {
+            vec![SourceMailbox {
+                name: "INBOX".to_owned(),
+                tags: HashSet::new(),
+            }]
+        } else {
+            file.mailboxes
+        };
+
+        let processed_action = match ProcessedAction::parse(
+            file.imap.processed_action.as_deref().unwrap_or("delete"),
+            file.imap
+                .archive_mailbox
+                .clone()
+                .unwrap_or_else(|| "Archive".to_owned()),
+        ) {
+            Some(action) => Some(action),
+            None => {
+                problems.invalid(
+                    "imap.processed_action",
+                    "TMBU_PROCESSED_ACTION",
+                    "must be \"archive\", \"seen\", or \"delete\"",
+                );
+                None
+            }
+        };
+
+        let review_rules = match &file.review_when {
+            Some(rules) => rules
+                .iter()
+                .filter_map(|rule| {
+                    let parsed = ReviewRule::parse(rule);
+                    if parsed.is_none() {
+                        problems.invalid(
+                            "review_when",
+                            "TMBU_REVIEW_WHEN",
+                            &format!(
+                                "has unknown rule {rule:#?} (must be \"no-tags\", \"no-link\", or \"enrichment-failed\")"
+                            ),
+                        );
+                    }
+                    parsed
+                })
+                .collect(),
+            None => ReviewRule::all(),
+        };
+
+        let quarantine = file.imap.failed_mailbox.clone().map(|mailbox| {
+            Quarantine::new(
+                mailbox,
+                file.failed_dir
+                    .unwrap_or_else(|| config_dir().join("failed")),
+            )
+        });
+
+        problems.into_result()?;
+
+        Ok(Self {
+            tag_dictionary: file
+                .tag_dictionary
+                .unwrap_or_else(|| zola_root.as_ref().unwrap().join("tags.toml")),
+            zola_root: zola_root.unwrap(),
+            mailboxes,
+            // The search criteria can narrow down which messages we look at
+            // (e.g. `UNSEEN TO tmbu+links@example.com`).
+            search: file.imap.search.clone().unwrap_or_else(|| "ALL".to_owned()),
+            batch_size: file.imap.batch_size.filter(|size| *size > 0).unwrap_or(50),
+            sender_filter: SenderFilter::new(file.allowed_senders),
+            quarantine,
+            processed_action: processed_action.unwrap(),
+            later_mailbox: file
+                .imap
+                .later_mailbox
+                .clone()
+                .unwrap_or_else(|| "Later".to_owned()),
+            interactive: true,
+            dry_run: false,
+            batch: false,
+            review_rules,
+            imap: file.imap,
+            oauth2: file.oauth2,
+        })
+    }
+
+    /// Reads and validates the IMAP settings, running `password_command` if
+    /// there is one. This is separate from `load` so that commands which
+    /// don't connect (e.g. `import`) don't need any of them.
+    pub fn imap(&self) -> Result<ImapConfig> {
+        let mut problems = Problems::default();
+        let imap = ImapConfig::from_file(&self.imap, &self.oauth2, &mut problems);
+        problems.into_result()?;
+
+        Ok(imap.unwrap())
+    }
+}
+
+impl ImapConfig {
+    fn from_file(
+        imap: &ImapSection,
+        oauth2: &OAuth2Section,
+        problems: &mut Problems,
+    ) -> Option<Self> {
+        let host = problems.require(imap.host.clone(), "imap.host", "TMBU_IMAP_HOST");
+        let username =
+            problems.require(imap.username.clone(), "imap.username", "TMBU_IMAP_USERNAME");
+
+        let security = match imap.security.as_deref().unwrap_or("tls") {
+            "tls" => Some(Security::Tls),
+            "starttls" => Some(Security::StartTls),
+            "plaintext" => Some(Security::Plaintext),
+            _ => {
+                problems.invalid(
+                    "imap.security",
+                    "TMBU_IMAP_SECURITY",
+                    "must be \"tls\", \"starttls\", or \"plaintext\"",
+                );
+                None
+            }
+        };
+
+        let auth = match imap.auth.as_deref().unwrap_or("password") {
+            "password" => password(imap, problems).map(Auth::Password),
+            "xoauth2" => OAuth2::from_file(oauth2, problems).map(Auth::XOAuth2),
+            _ => {
+                problems.invalid(
+                    "imap.auth",
+                    "TMBU_IMAP_AUTH",
+                    "must be \"password\" or \"xoauth2\"",
+                );
+                None
+            }
+        };
+
+        Some(Self {
+            host: host?,
+            port: imap.port,
+            security: security?,
+            ca_file: imap.ca_file.clone(),
+            accept_invalid_certs_for: imap.accept_invalid_certs_for.clone(),
+            username: username?,
+            auth: auth?,
+        })
+    }
+}
+
+impl OAuth2 {
+    fn from_file(oauth2: &OAuth2Section, problems: &mut Problems) -> Option<Self> {
+        let token_url = problems.require(
+            oauth2.token_url.clone(),
+            "oauth2.token_url",
+            "TMBU_OAUTH2_TOKEN_URL",
+        );
+        let client_id = problems.require(
+            oauth2.client_id.clone(),
+            "oauth2.client_id",
+            "TMBU_OAUTH2_CLIENT_ID",
+        );
+        let refresh_token = problems.require(
+            oauth2.refresh_token.clone(),
+            "oauth2.refresh_token",
+            "TMBU_OAUTH2_REFRESH_TOKEN",
+        );
+
+        Some(Self {
+            token_url: token_url?,
+            client_id: client_id?,
+            client_secret: oauth2.client_secret.clone(),
+            refresh_token: refresh_token?,
+            scope: oauth2.scope.clone(),
+            cache_path: oauth2
+                .token_cache
+                .clone()
+                .unwrap_or_else(|| config_dir().join("oauth2_token.json")),
+        })
+    }
+}
+
+/// Where `tw` keeps its configuration and other local state.
+pub(crate) fn config_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_default()
+        .join(".config")
+        .join("tw")
+}
+
+// The password can be given directly, or (better) fetched from a password
+// manager by running a command such as `pass show mail/tmbu` or
+// `security find-generic-password -s tmbu -w`.
+fn password(imap: &ImapSection, problems: &mut Problems) -> Option<String> {
+    if let Some(password) = &imap.password {
+        return Some(password.clone());
+    }
+
+    let command = match &imap.password_command {
+        Some(command) => command,
+        None => {
+            problems.missing(
+                "imap.password or imap.password_command",
+                "TMBU_IMAP_PASSWORD or TMBU_IMAP_PASSWORD_COMMAND",
+            );
+            return None;
+        }
+    };
+
+    match Command::new("sh").arg("-c").arg(command).output() {
+        Ok(output) if output.status.success() => {
+            let password = String::from_utf8_lossy(&output.stdout);
+            Some(password.trim_end_matches(['\r', '\n']).to_owned())
+        }
+        Ok(output) => {
+            problems.invalid(
+                "imap.password_command",
+                "TMBU_IMAP_PASSWORD_COMMAND",
+                &format!(
+                    "failed: {stderr}",
+                    stderr = String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            );
+            None
+        }
+        Err(e) => {
+            problems.invalid(
+                "imap.password_command",
+                "TMBU_IMAP_PASSWORD_COMMAND",
+                &format!("could not be run: {e}"),
+            );
+            None
+        }
+    }
+}
+
+// Collects everything that's wrong with the configuration so it can all be
+// reported at once.
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn require<T>(&mut self, value: Option<T>, key: &str, env_var: &str) -> Option<T> {
+        if value.is_none() {
+            self.missing(key, env_var);
+        }
+        value
+    }
+
+    fn missing(&mut self, key: &str, env_var: &str) {
+        self.0.push(format!("{key} is missing (or set {env_var})"));
+    }
+
+    fn invalid(&mut self, key: &str, env_var: &str, reason: &str) {
+        self.0.push(format!("{key} ({env_var}) {reason}"));
+    }
+
+    fn into_result(self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(self.0))
+        }
+    }
+}
+
+// The config file as written. Everything is optional here; `Config::load`
+// decides what's actually required.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    zola_root: Option<PathBuf>,
+    allowed_senders: Vec<String>,
+    failed_dir: Option<PathBuf>,
+    tag_dictionary: Option<PathBuf>,
+    review_when: Option<Vec<String>>,
+    imap: ImapSection,
+    mailboxes: Vec<SourceMailbox>,
+    oauth2: OAuth2Section,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ImapSection {
+    host: Option<String>,
+    port: Option<u16>,
+    security: Option<String>,
+    ca_file: Option<PathBuf>,
+    accept_invalid_certs_for: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    password_command: Option<String>,
+    auth: Option<String>,
+    search: Option<String>,
+    batch_size: Option<usize>,
+    failed_mailbox: Option<String>,
+    processed_action: Option<String>,
+    archive_mailbox: Option<String>,
+    later_mailbox: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OAuth2Section {
+    token_url: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+    token_cache: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    // A missing default config file is fine (everything might come from the
+    // environment), but one that was asked for by name must exist, and one
+    // we can't read or parse is never fine.
+    fn read(path: &Path, optional: bool) -> Result<Self> {
+        let toml = match fs::read_to_string(path) {
+            Ok(toml) => toml,
+            Err(e) if optional && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(Error::Config(vec![format!(
+                    "unable to read {path:#?}: {e}"
+                )]));
+            }
+        };
+
+        toml::from_str(&toml).map_err(|e| Error::Config(vec![format!("{path:#?}: {e}")]))
+    }
+
+    fn apply_env(&mut self, problems: &mut Problems) {
+        env_override(&mut self.zola_root, "zola_root", "TMBU_ZOLA_ROOT", problems);
+        env_override(
+            &mut self.failed_dir,
+            "failed_dir",
+            "TMBU_FAILED_DIR",
+            problems,
+        );
+        env_override(
+            &mut self.tag_dictionary,
+            "tag_dictionary",
+            "TMBU_TAG_DICTIONARY",
+            problems,
+        );
+
+        if let Ok(senders) = env::var("TMBU_ALLOWED_SENDERS") {
+            self.allowed_senders = senders.split(',').map(|s| s.to_owned()).collect();
+        }
+
+        if let Ok(rules) = env::var("TMBU_REVIEW_WHEN") {
+            self.review_when = Some(
+                rules
+                    .split(',')
+                    .map(|s| s.trim().to_owned())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            );
+        }
+
+        if let Ok(mailboxes) = env::var("TMBU_IMAP_MAILBOXES") {
+            self.mailboxes = SourceMailbox::parse_list(&mailboxes);
+        }
+
+        let imap = &mut self.imap;
+        env_override(&mut imap.host, "imap.host", "TMBU_IMAP_HOST", problems);
+        env_override(&mut imap.port, "imap.port", "TMBU_IMAP_PORT", problems);
+        env_override(
+            &mut imap.security,
+            "imap.security",
+            "TMBU_IMAP_SECURITY",
+            problems,
+        );
+        env_override(
+            &mut imap.ca_file,
+            "imap.ca_file",
+            "TMBU_IMAP_CA_FILE",
+            problems,
+        );
+        env_override(
+            &mut imap.accept_invalid_certs_for,
+            "imap.accept_invalid_certs_for",
+            "TMBU_IMAP_ACCEPT_INVALID_CERTS_FOR",
+            problems,
+        );
+        env_override(
+            &mut imap.username,
+            "imap.username",
+            "TMBU_IMAP_USERNAME",
+            problems,
+        );
+        env_override(
+            &mut imap.password,
+            "imap.password",
+            "TMBU_IMAP_PASSWORD",
+            problems,
+        );
+        env_override(
+            &mut imap.password_command,
+            "imap.password_command",
+            "TMBU_IMAP_PASSWORD_COMMAND",
+            problems,
+        );
+        env_override(&mut imap.auth, "imap.auth", "TMBU_IMAP_AUTH", problems);
+        env_override(
+            &mut imap.search,
+            "imap.search",
+            "TMBU_IMAP_SEARCH",
+            problems,
+        );
+        env_override(
+            &mut imap.batch_size,
+            "imap.batch_size",
+            "TMBU_IMAP_BATCH_SIZE",
+            problems,
+        );
+        env_override(
+            &mut imap.failed_mailbox,
+            "imap.failed_mailbox",
+            "TMBU_IMAP_FAILED_MAILBOX",
+            problems,
+        );
+        env_override(
+            &mut imap.processed_action,
+            "imap.processed_action",
+            "TMBU_PROCESSED_ACTION",
+            problems,
+        );
+        env_override(
+            &mut imap.archive_mailbox,
+            "imap.archive_mailbox",
+            "TMBU_IMAP_ARCHIVE_MAILBOX",
+            problems,
+        );
+        env_override(
+            &mut imap.later_mailbox,
+            "imap.later_mailbox",
+            "TMBU_IMAP_LATER_MAILBOX",
+            problems,
+        );
+
+        let oauth2 = &mut self.oauth2;
+        env_override(
+            &mut oauth2.token_url,
+            "oauth2.token_url",
+            "TMBU_OAUTH2_TOKEN_URL",
+            problems,
+        );
+        env_override(
+            &mut oauth2.client_id,
+            "oauth2.client_id",
+            "TMBU_OAUTH2_CLIENT_ID",
+            problems,
+        );
+        env_override(
+            &mut oauth2.client_secret,
+            "oauth2.client_secret",
+            "TMBU_OAUTH2_CLIENT_SECRET",
+            problems,
+        );
+        env_override(
+            &mut oauth2.refresh_token,
+            "oauth2.refresh_token",
+            "TMBU_OAUTH2_REFRESH_TOKEN",
+            problems,
+        );
+        env_override(
+            &mut oauth2.scope,
+            "oauth2.scope",
+            "TMBU_OAUTH2_SCOPE",
+            problems,
+        );
+        env_override(
+            &mut oauth2.token_cache,
+            "oauth2.token_cache",
+            "TMBU_OAUTH2_TOKEN_CACHE",
+            problems,
+        );
+    }
+}
+
+fn env_override<T: FromStr>(
+    value: &mut Option<T>,
+    key: &str,
+    env_var: &str,
+    problems: &mut Problems,
+) where
+    T::Err: fmt::Display,
+{
+    if let Ok(s) = env::var(env_var) {
+        match s.parse() {
+            Ok(v) => *value = Some(v),
+            Err(e) => problems.invalid(key, env_var, &format!("is invalid: {e}")),
+        }
+    }
+}
+
+impl SourceMailbox {
+    // Parses a comma-separated list of mailboxes, each optionally followed by
+    // hashtags: `INBOX, Links/Rust #rust, Links/Photography #photography`.
+    fn parse_list(s: &str) -> Vec<Self> {
+        s.split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split('#');
+
+                let name = parts.next()?.trim();
+                if name.is_empty() {
+                    return None;
+                }
+
+                let tags = parts
+                    .map(|tag| tag.trim().to_owned())
+                    .filter(|tag| !tag.is_empty())
+                    .collect();
+
+                Some(Self {
+                    name: name.to_owned(),
+                    tags,
+                })
+            })
+            .collect()
+    }
+}
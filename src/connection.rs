@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use imap::{Client, Session};
+use native_tls::{Certificate, TlsConnector, TlsStream};
+
+use crate::{
+    config::{Auth, ImapConfig},
+    error::Result,
+    oauth2::XOAuth2,
+};
+
+/// How to secure the connection to the IMAP server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Security {
+    /// TLS from the start, usually on port 993.
+    Tls,
+
+    /// Plain connection upgraded with STARTTLS, usually on port 143.
+    StartTls,
+
+    /// No encryption at all. Only for testing against a local server.
+    Plaintext,
+}
+
+/// A session with the IMAP server, which may or may not be encrypted.
+///
+/// The two kinds of `Session` are different types, so use `with_session!` to
+/// work with whichever one this is.
+pub(crate) enum ImapSession {
+    Tls(Session<TlsStream<TcpStream>>),
+    Plain(Session<TcpStream>),
+}
+
+/// Evaluates `$body` with `$session` bound to the `Session` inside an
+/// `ImapSession`, whatever kind of stream it uses.
+macro_rules! with_session {
+    ($imap_session:expr, |$session:ident| $body:expr) => {
+        match $imap_session {
+            $crate::connection::ImapSession::Tls(mut $session) => $body,
+            $crate::connection::ImapSession::Plain(mut $session) => $body,
+        }
+    };
+}
+
+pub(crate) use with_session;
+
+/// Connects and logs in to the IMAP server.
+pub(crate) fn connect(imap: &ImapConfig) -> Result<ImapSession> {
+    // Adapted from example at https://github.com/jonhoo/rust-imap/tree/v2.4.1#readme.
+
+    let domain = &imap.host;
+    let security = imap.security;
+
+    if security == Security::Plaintext {
+        eprintln!("WARNING: Connecting to {domain} without encryption");
+    }
+
+    let port = imap.port.unwrap_or(match security {
+        Security::Tls => 993,
+        _ => 143,
+    });
+
+    let tcp = TcpStream::connect((domain.as_str(), port)).map_err(imap::Error::Io)?;
+
+    let imap_session = match security {
+        Security::Tls => {
+            let tls = tls_connector(imap)?
+                .connect(domain, tcp)
+                .map_err(imap::Error::TlsHandshake)?;
+
+            let mut client = Client::new(tls);
+            client.read_greeting()?;
+            ImapSession::Tls(log_in(client, imap)?)
+        }
+        Security::StartTls => {
+            let mut client = Client::new(tcp);
+            client.read_greeting()?;
+
+            let client = client.secure(domain, &tls_connector(imap)?)?;
+            ImapSession::Tls(log_in(client, imap)?)
+        }
+        Security::Plaintext => {
+            let mut client = Client::new(tcp);
+            client.read_greeting()?;
+            ImapSession::Plain(log_in(client, imap)?)
+        }
+    };
+
+    Ok(imap_session)
+}
+
+// The client we have here is unauthenticated. To do anything useful with the
+// e-mails, we need to log in.
+fn log_in<T: Read + Write>(client: Client<T>, imap: &ImapConfig) -> Result<Session<T>> {
+    let imap_session = match &imap.auth {
+        Auth::Password(password) => client.login(&imap.username, password).map_err(|(e, _)| e)?,
+        Auth::XOAuth2(oauth2) => {
+            let xoauth2 = XOAuth2 {
+                user: imap.username.clone(),
+                access_token: oauth2.access_token()?,
+            };
+
+            client
+                .authenticate("XOAUTH2", &xoauth2)
+                .map_err(|(e, _)| e)?
+        }
+    };
+
+    Ok(imap_session)
+}
+
+fn tls_connector(imap: &ImapConfig) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder();
+
+    if let Some(ca_file) = &imap.ca_file {
+        let pem = fs::read(ca_file).map_err(imap::Error::Io)?;
+        builder.add_root_certificate(Certificate::from_pem(&pem).map_err(imap::Error::Tls)?);
+    }
+
+    // Only ever for a host that was named explicitly, so that this can't
+    // accidentally apply to some other server.
+    if imap.accept_invalid_certs_for.as_ref() == Some(&imap.host) {
+        eprintln!(
+            "WARNING: Not verifying the TLS certificate for {host}",
+            host = imap.host
+        );
+        builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(builder.build().map_err(imap::Error::Tls)?)
+}
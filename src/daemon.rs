@@ -0,0 +1,71 @@
+use std::{thread, time::Duration};
+
+use imap::extensions::idle::WaitOutcome;
+
+use crate::{
+    config::{Config, ImapConfig},
+    connection::{connect, with_session},
+    error::Result,
+    process_mailbox,
+    report::Report,
+    tags::TagDictionary,
+};
+
+// Servers may drop an IDLE after 30 minutes (RFC 2177), so re-issue it well
+// before then. This is also how often mailboxes other than the first are
+// checked, since IDLE only watches the selected mailbox.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// How long to wait before reconnecting after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Runs forever, filing posts as new mail arrives.
+pub(crate) fn run(config: &Config, imap: &ImapConfig, dictionary: &mut TagDictionary) -> ! {
+    loop {
+        if let Err(e) = watch(config, imap, dictionary) {
+            eprintln!("Lost connection to IMAP server: {e}");
+        }
+
+        thread::sleep(RECONNECT_DELAY);
+        println!("Reconnecting ...");
+    }
+}
+
+// Processes everything that's waiting, then waits for more. Only returns if
+// something goes wrong with the connection.
+fn watch(config: &Config, imap: &ImapConfig, dictionary: &mut TagDictionary) -> Result<()> {
+    with_session!(connect(imap)?, |imap_session| {
+        let has_idle = imap_session.capabilities()?.has_str("IDLE");
+
+        loop {
+            let mut report = Report::default();
+
+            for source in &config.mailboxes {
+                if let Err(e) =
+                    process_mailbox(&mut imap_session, source, config, dictionary, &mut report)
+                {
+                    eprintln!("Unable to process mailbox {name}: {e}", name = source.name);
+                }
+            }
+
+            if !report.is_empty() {
+                print!("\n{report}");
+            }
+
+            let watched = &config.mailboxes[0].name;
+            imap_session.select(watched)?;
+
+            if has_idle {
+                match imap_session.idle()?.wait_with_timeout(IDLE_TIMEOUT)? {
+                    WaitOutcome::MailboxChanged => println!("New mail in {watched}"),
+                    WaitOutcome::TimedOut => (),
+                }
+            } else {
+                thread::sleep(IDLE_TIMEOUT);
+            }
+
+            // Make sure the connection is still alive before we go around again.
+            imap_session.noop()?;
+        }
+    })
+}
@@ -0,0 +1,45 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// Everything that can go wrong while turning one e-mail into a blog post.
+///
+/// Most of these are scoped to a single message, so `main` can report the
+/// error and move on to the next message rather than abandoning the run.
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    #[error("configuration problems:\n  {}", .0.join("\n  "))]
+    Config(Vec<String>),
+
+    #[error("unable to parse message: {0}")]
+    Parse(String),
+
+    #[error("unable to fetch {url}: {reason}")]
+    Network { url: String, reason: String },
+
+    #[error("unable to render post: {0}")]
+    Render(#[from] io::Error),
+
+    #[error("unable to read {}: {source}", .path.display())]
+    Import { path: PathBuf, source: io::Error },
+
+    #[error("unable to write {}: {source}", .path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("git {0}")]
+    Git(String),
+
+    #[error("IMAP error: {0}")]
+    Imap(#[from] imap::Error),
+}
+
+impl Error {
+    pub(crate) fn network<U: ToString, R: ToString>(url: U, reason: R) -> Self {
+        Self::Network {
+            url: url.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,110 @@
+use crate::mime::Part;
+
+/// Decides which messages are allowed to become posts.
+///
+/// Anything that lands in the mailbox could otherwise turn into a blog post,
+/// so only messages from known senders are processed.
+pub(crate) struct SenderFilter {
+    allowed: Vec<String>,
+}
+
+impl SenderFilter {
+    /// Takes a list of addresses (`me@example.com`) and domains
+    /// (`example.com` or `@example.com`). Returns `None` (allow everything)
+    /// if the list is empty.
+    pub fn new(allowed: Vec<String>) -> Option<Self> {
+        let allowed: Vec<String> = allowed
+            .iter()
+            .map(|sender| sender.trim().to_ascii_lowercase())
+            .filter(|sender| !sender.is_empty())
+            .collect();
+
+        if allowed.is_empty() {
+            return None;
+        }
+
+        Some(Self { allowed })
+    }
+
+    /// Returns `Err` with a reason if the message should be ignored.
+    pub fn check(&self, raw: &[u8]) -> Result<(), String> {
+        let root = Part::parse(raw);
+
+        let from = root
+            .decoded_header("From")
+            .ok_or_else(|| "no From header".to_owned())?;
+
+        let address = address_from(&from);
+        let domain = address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default();
+
+        if !self.allowed.iter().any(|allowed| {
+            if allowed.contains('@') && !allowed.starts_with('@') {
+                *allowed == address
+            } else {
+                allowed.trim_start_matches('@') == domain
+            }
+        }) {
+            return Err(format!("sender {address} is not allowed"));
+        }
+
+        // The From header is trivially forged, so if our mail server checked
+        // DKIM signatures, insist on one that matches the sender's domain.
+        if let Some(results) = root.header("Authentication-Results") {
+            if !dkim_passes(results, domain) {
+                return Err(format!("no passing DKIM signature for {domain}"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn address_from(from: &str) -> String {
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    };
+
+    address.trim().to_ascii_lowercase()
+}
+
+// Looks at the `dkim=` results in an Authentication-Results header (RFC 8601).
+// Returns true if there are no DKIM results at all (the server didn't check)
+// or if one of them passed for the given domain or a parent of it.
+fn dkim_passes(results: &str, domain: &str) -> bool {
+    let dkim_results: Vec<&str> = results
+        .split(';')
+        .map(str::trim)
+        .filter(|result| result.to_ascii_lowercase().starts_with("dkim="))
+        .collect();
+
+    if dkim_results.is_empty() {
+        return true;
+    }
+
+    dkim_results.iter().any(|result| {
+        let result = result.to_ascii_lowercase();
+
+        if !result.starts_with("dkim=pass") {
+            return false;
+        }
+
+        result.split_whitespace().any(|prop| {
+            let signer = prop.strip_prefix("header.d=").or_else(|| {
+                prop.strip_prefix("header.i=")
+                    .map(|i| i.trim_start_matches('@'))
+            });
+
+            match signer {
+                Some(signer) => {
+                    let signer = signer.trim_end_matches(|c: char| !c.is_alphanumeric());
+                    domain == signer || domain.ends_with(&format!(".{signer}"))
+                }
+                None => false,
+            }
+        })
+    })
+}
@@ -0,0 +1,119 @@
+// Converts the `text/html` part of a message to Markdown for those mail
+// clients (mostly share sheets) that don't send a `text/plain` alternative.
+//
+// This is deliberately simple: share sheet messages are a paragraph or two
+// with a link, not arbitrary web pages.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Converts an HTML fragment to Markdown.
+///
+/// The first `<a href>` in the document is returned separately as the post
+/// link and is replaced in the Markdown by its link text (or dropped entirely
+/// if its link text is just the URL). Later links become inline Markdown
+/// links.
+pub(crate) fn to_markdown(html: &str) -> (Option<String>, String) {
+    lazy_static! {
+        static ref INVISIBLE: Regex =
+            Regex::new(r#"(?is)<(head|style|script|title)\b[^>]*>.*?</(head|style|script|title)>"#)
+                .unwrap();
+        static ref COMMENT: Regex = Regex::new(r#"(?s)<!--.*?-->"#).unwrap();
+        static ref BR: Regex = Regex::new(r#"(?i)<br\s*/?>"#).unwrap();
+        static ref BLOCK: Regex =
+            Regex::new(r#"(?i)</?(p|div|h[1-6]|ul|ol|blockquote|table|tr)\b[^>]*>"#).unwrap();
+        static ref LI: Regex = Regex::new(r#"(?i)<li\b[^>]*>"#).unwrap();
+        static ref A_HREF: Regex =
+            Regex::new(r#"(?is)<a\b[^>]*?\bhref\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a>"#).unwrap();
+        static ref TAG: Regex = Regex::new(r#"(?s)<[^>]*>"#).unwrap();
+        static ref SPACES: Regex = Regex::new(r#"[ \t\u{a0}]+"#).unwrap();
+        static ref BLANK_LINES: Regex = Regex::new(r#"\n{3,}"#).unwrap();
+    }
+
+    let html = INVISIBLE.replace_all(html, "");
+    let html = COMMENT.replace_all(&html, "");
+
+    // HTML whitespace (including newlines) is insignificant, so collapse it
+    // before inserting the line breaks implied by the markup.
+    let html = html.replace(['\r', '\n'], " ");
+    let html = BR.replace_all(&html, "\n");
+    let html = BLOCK.replace_all(&html, "\n\n");
+    let html = LI.replace_all(&html, "\n* ");
+
+    let mut link: Option<String> = None;
+
+    let html = A_HREF.replace_all(&html, |captures: &Captures| {
+        let href = decode_entities(captures[1].trim());
+        let text = TAG.replace_all(&captures[2], "");
+        let text = decode_entities(text.trim());
+
+        if link.is_none() && !href.starts_with('#') && !href.starts_with("mailto:") {
+            link = Some(href.clone());
+
+            if text == href {
+                "".to_owned()
+            } else {
+                text
+            }
+        } else if text.is_empty() || text == href {
+            href
+        } else {
+            format!("[{text}]({href})")
+        }
+    });
+
+    let text = TAG.replace_all(&html, "");
+    let text = decode_entities(&text);
+
+    let text = text
+        .lines()
+        .map(|line| SPACES.replace_all(line, " ").trim().to_owned())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let text = BLANK_LINES.replace_all(&text, "\n\n").trim().to_owned();
+
+    (link, text)
+}
+
+fn decode_entities(s: &str) -> String {
+    lazy_static! {
+        static ref ENTITY: Regex =
+            Regex::new(r#"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);"#).unwrap();
+    }
+
+    ENTITY
+        .replace_all(s, |captures: &Captures| {
+            let entity = &captures[1];
+
+            let c = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    "lsquo" => Some('‘'),
+                    "rsquo" => Some('’'),
+                    "ldquo" => Some('“'),
+                    "rdquo" => Some('”'),
+                    _ => None,
+                }
+            };
+
+            c.map(String::from)
+                .unwrap_or_else(|| captures[0].to_owned())
+        })
+        .to_string()
+}
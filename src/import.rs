@@ -0,0 +1,99 @@
+// Reads messages exported from another mail client, either as a directory of
+// `.eml` files or as a single mbox archive, so that a backlog can be filed
+// without going through IMAP.
+
+use std::{fs, path::Path};
+
+use crate::error::{Error, Result};
+
+/// A message read from disk, along with a name to report it by.
+pub(crate) struct ImportedMessage {
+    pub(crate) name: String,
+    pub(crate) raw: Vec<u8>,
+}
+
+/// Reads every message at `path`.
+///
+/// A directory is read as a set of `.eml` files, in file name order. A file
+/// is read as an mbox archive if it starts with a `From ` line, and as a
+/// single message otherwise.
+pub(crate) fn read_messages(path: &Path) -> Result<Vec<ImportedMessage>> {
+    let read_error = |source| Error::Import {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if path.is_dir() {
+        let mut paths = fs::read_dir(path)
+            .map_err(read_error)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("eml"))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+
+        paths.sort();
+
+        return paths
+            .into_iter()
+            .map(|path| {
+                let raw = fs::read(&path).map_err(|source| Error::Import {
+                    path: path.clone(),
+                    source,
+                })?;
+
+                Ok(ImportedMessage {
+                    name: path.display().to_string(),
+                    raw,
+                })
+            })
+            .collect();
+    }
+
+    let raw = fs::read(path).map_err(read_error)?;
+    let name = path.display().to_string();
+
+    if !raw.starts_with(b"From ") {
+        return Ok(vec![ImportedMessage { name, raw }]);
+    }
+
+    Ok(split_mbox(&raw)
+        .into_iter()
+        .enumerate()
+        .map(|(index, raw)| ImportedMessage {
+            name: format!("{name} (message {n})", n = index + 1),
+            raw,
+        })
+        .collect())
+}
+
+// Each message in an mbox archive starts with a `From ` line, which isn't
+// part of the message. Lines in the body that would look like one are
+// escaped with a leading `>`, which we remove again (the "mboxrd" convention).
+fn split_mbox(raw: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages: Vec<Vec<u8>> = vec![];
+
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        if line.starts_with(b"From ") {
+            messages.push(vec![]);
+            continue;
+        }
+
+        // Anything before the first `From ` line isn't a message.
+        let message = match messages.last_mut() {
+            Some(message) => message,
+            None => continue,
+        };
+
+        let unquoted = line.iter().position(|b| *b != b'>').unwrap_or(0);
+        if unquoted > 0 && line[unquoted..].starts_with(b"From ") {
+            message.extend_from_slice(&line[1..]);
+        } else {
+            message.extend_from_slice(line);
+        }
+    }
+
+    messages
+}
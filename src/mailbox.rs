@@ -0,0 +1,65 @@
+use std::io::{Read, Write};
+
+use imap::{types::Uid, Session};
+
+use crate::error::Result;
+
+/// Moves a message to another mailbox, creating that mailbox if needed.
+///
+/// Uses `UID MOVE` if the server supports it. Otherwise the message is
+/// copied and flagged `\Deleted`, in which case this returns true: the
+/// message is still in this mailbox until it's expunged.
+pub(crate) fn move_message<T: Read + Write>(
+    imap_session: &mut Session<T>,
+    uid: Uid,
+    mailbox: &str,
+) -> Result<bool> {
+    let uid = uid.to_string();
+    let has_move = imap_session.capabilities()?.has_str("MOVE");
+
+    let transfer = |imap_session: &mut Session<T>| {
+        if has_move {
+            imap_session.uid_mv(&uid, mailbox)
+        } else {
+            imap_session.uid_copy(&uid, mailbox)
+        }
+    };
+
+    if transfer(imap_session).is_err() {
+        // Most likely the mailbox doesn't exist yet.
+        imap_session.create(mailbox)?;
+        transfer(imap_session)?;
+    }
+
+    if !has_move {
+        imap_session.uid_store(&uid, "+FLAGS (\\Deleted)")?;
+    }
+
+    Ok(!has_move)
+}
+
+/// Permanently removes the given messages, which must already be flagged
+/// `\Deleted`, from the selected mailbox.
+///
+/// Only servers with `UIDPLUS` can expunge specific messages. A plain
+/// `EXPUNGE` would also remove anything I've deleted in my mail client but
+/// not yet expunged, so without `UIDPLUS` the messages are left flagged for
+/// the mail client to deal with. (Searches skip deleted messages, so they
+/// won't be processed again.)
+pub(crate) fn expunge<T: Read + Write>(imap_session: &mut Session<T>, uids: &[Uid]) -> Result<()> {
+    if uids.is_empty() || !imap_session.capabilities()?.has_str("UIDPLUS") {
+        return Ok(());
+    }
+
+    imap_session.uid_expunge(uid_set(uids))?;
+
+    Ok(())
+}
+
+/// Formats a list of UIDs as an IMAP sequence set.
+pub(crate) fn uid_set(uids: &[Uid]) -> String {
+    uids.iter()
+        .map(|uid| uid.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
@@ -1,67 +1,465 @@
-use std::env;
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    process,
+};
 
+use clap::Parser;
+use imap::{types::Uid, Session};
+
+use crate::{
+    cli::{Cli, Command},
+    config::{Config, SourceMailbox},
+    connection::{connect, with_session},
+    error::{Error, Result},
+    post::Post,
+    report::Report,
+    review::Disposition,
+    tags::TagDictionary,
+};
+
+mod cli;
+mod config;
+mod connection;
+mod daemon;
+mod error;
+mod filter;
+mod html;
+mod import;
+mod mailbox;
 mod message;
+mod mime;
+mod oauth2;
 mod post;
+mod processed;
+mod quarantine;
 mod read_line;
+mod report;
+mod review;
+mod suggest;
+mod tags;
 
 fn main() {
-    // Adapted from example at https://github.com/jonhoo/rust-imap/tree/v2.4.1#readme.
+    if let Err(e) = try_main() {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+fn try_main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let mut config = Config::load(cli.config.as_deref())?;
+
+    config.batch = cli.yes;
+
+    let mut dictionary = TagDictionary::load(&config.tag_dictionary)?;
+
+    dictionary.scan_site(&config.zola_root.join("content"));
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(&config, &mut dictionary),
+        Command::DryRun => {
+            // Nothing is kept, so there's no point asking any questions.
+            config.interactive = false;
+            config.dry_run = true;
+            run(&config, &mut dictionary)
+        }
+        Command::Preview { uid, mailbox } => preview(&config, &dictionary, uid, mailbox),
+        Command::List => list(&config),
+        Command::Import {
+            path,
+            tags,
+            dry_run,
+        } => {
+            if dry_run {
+                config.interactive = false;
+                config.dry_run = true;
+            }
+            import(&config, &mut dictionary, &path, &tags.into_iter().collect())
+        }
+        Command::Daemon => {
+            // Nobody is watching, so don't ask any questions.
+            config.interactive = false;
+            let imap = config.imap()?;
+            daemon::run(&config, &imap, &mut dictionary);
+        }
+    }
+}
+
+fn run(config: &Config, dictionary: &mut TagDictionary) -> Result<()> {
+    with_session!(connect(&config.imap()?)?, |imap_session| {
+        let mut report = Report::default();
+
+        for source in &config.mailboxes {
+            if let Err(e) =
+                process_mailbox(&mut imap_session, source, config, dictionary, &mut report)
+            {
+                eprintln!("Unable to process mailbox {name}: {e}", name = source.name);
+            }
+        }
+
+        // Be nice to the server and log out.
+        imap_session.logout()?;
+
+        print!("\n{report}");
+
+        Ok(())
+    })
+}
+
+fn list(config: &Config) -> Result<()> {
+    with_session!(connect(&config.imap()?)?, |imap_session| {
+        for source in &config.mailboxes {
+            let result = for_each_pending(&mut imap_session, source, config, |_, uid, raw| {
+                match message::Message::parse(raw) {
+                    Ok(message) => {
+                        println!(
+                            "{uid:>6}  {date}  {subject}",
+                            date = message.date.format("%Y-%m-%d %H:%M"),
+                            subject = message.subject
+                        );
+                        if let Some(link) = message.link {
+                            println!("        {link}");
+                        }
+                    }
+                    Err(e) => println!("{uid:>6}  (unable to parse: {e})"),
+                }
+                Ok(())
+            });
 
-    let domain = env::var("TMBU_IMAP_HOST").unwrap();
-    let tls = native_tls::TlsConnector::builder().build().unwrap();
+            if let Err(e) = result {
+                eprintln!("Unable to list mailbox {name}: {e}", name = source.name);
+            }
+        }
+
+        imap_session.logout()?;
+
+        Ok(())
+    })
+}
+
+fn preview(
+    config: &Config,
+    dictionary: &TagDictionary,
+    uid: Uid,
+    mailbox: Option<String>,
+) -> Result<()> {
+    // Use the configured default tags if the message is in one of the
+    // configured mailboxes.
+    let source = match mailbox {
+        Some(name) => config
+            .mailboxes
+            .iter()
+            .find(|source| source.name == name)
+            .cloned()
+            .unwrap_or(SourceMailbox {
+                name,
+                tags: HashSet::new(),
+            }),
+        None => config.mailboxes[0].clone(),
+    };
+
+    with_session!(connect(&config.imap()?)?, |imap_session| {
+        imap_session.select(&source.name)?;
 
-    // We pass in the domain twice to check that the server's TLS
-    // certificate is valid for the domain we're connecting to.
-    let client = imap::connect((domain.clone(), 993), domain, &tls).unwrap();
+        let messages = imap_session.uid_fetch(uid.to_string(), "(UID FLAGS BODY.PEEK[])")?;
+        let imap_message = messages.iter().next().ok_or_else(|| {
+            Error::Parse(format!(
+                "no message with UID {uid} in {mailbox}",
+                mailbox = source.name
+            ))
+        })?;
 
-    // The client we have here is unauthenticated.
-    // To do anything useful with the e-mails, we need to log in.
-    let username = env::var("TMBU_IMAP_USERNAME").unwrap();
-    let password = env::var("TMBU_IMAP_PASSWORD").unwrap();
+        let raw = imap_message
+            .body()
+            .ok_or_else(|| Error::Parse("message did not have a body".to_owned()))?;
 
-    let mut imap_session = client.login(username, password).unwrap();
+        let post = prepare_post(raw, &source.tags, dictionary)?;
+        print_post(&post);
 
-    // Read contents of primary inbox.
-    imap_session.select("INBOX").unwrap();
+        if !post.suggested_tags().is_empty() {
+            println!(
+                "\nSuggested tags: {suggested}",
+                suggested = post.suggested_tags().join(", ")
+            );
+        }
 
-    // Read first 500 message in inbox.
-    let messages = imap_session.fetch("1:500", "RFC822").unwrap();
+        imap_session.logout()?;
+
+        Ok(())
+    })
+}
+
+fn import(
+    config: &Config,
+    dictionary: &mut TagDictionary,
+    path: &Path,
+    default_tags: &HashSet<String>,
+) -> Result<()> {
+    let messages = import::read_messages(path)?;
 
     let count = messages.len();
-    println!("Found {count} messages in inbox");
-
-    for imap_message in messages.iter() {
-        // Pick apart the important parts of the IMAP message.
-        let message = message::Message::from(&imap_message);
-
-        let message = match message {
-            Some(message) => message,
-            None => {
-                let body = imap_message.body().expect("Message did not have a body!");
-                let body = std::str::from_utf8(body)
-                    .expect("Message was not valid utf-8")
-                    .to_string();
-
-                eprintln!("Failed to parse message\n\n{body}\n\n");
-                panic!("Failed to parse message");
+    println!("Found {count} messages in {path}", path = path.display());
+
+    let mut report = Report::default();
+
+    for message in messages {
+        if let Err(e) = process_message(
+            &message.raw,
+            &message.name,
+            default_tags,
+            config,
+            dictionary,
+            &mut report,
+        ) {
+            eprintln!("Skipping {name}: {e}", name = message.name);
+            report.failed(message.name, e.to_string());
+        }
+    }
+
+    print!("\n{report}");
+
+    Ok(())
+}
+
+fn process_mailbox<T: Read + Write>(
+    imap_session: &mut Session<T>,
+    source: &SourceMailbox,
+    config: &Config,
+    dictionary: &mut TagDictionary,
+    report: &mut Report,
+) -> Result<()> {
+    // Messages this run flagged `\Deleted`. Only these are expunged.
+    let mut deleted = Vec::new();
+
+    let result = for_each_pending(imap_session, source, config, |imap_session, uid, raw| {
+        let name = format!("message {uid} in {mailbox}", mailbox = source.name);
+
+        match process_message(raw, &name, &source.tags, config, dictionary, report) {
+            Ok(_) if config.dry_run => {}
+            Ok(Disposition::Posted(post_path)) => {
+                if config
+                    .processed_action
+                    .apply(imap_session, uid, &post_path)?
+                {
+                    deleted.push(uid);
+                }
+            }
+            Ok(Disposition::Skip) => {
+                // Leave it where it is for next time.
+            }
+            Ok(Disposition::Discard) => {
+                imap_session.uid_store(uid.to_string(), "+FLAGS (\\Deleted)")?;
+                deleted.push(uid);
+            }
+            Ok(Disposition::Defer) => {
+                imap_session.uid_store(uid.to_string(), "+FLAGS (\\Flagged)")?;
+                if mailbox::move_message(imap_session, uid, &config.later_mailbox)? {
+                    deleted.push(uid);
+                }
             }
-        };
-
-        // Now turn the parsed message into a pending Zola post.
-        let mut post = post::Post::from(message);
-        post.update_if_mastodon_link();
-        post.update_if_unsigned_mastodon_link();
-        post.add_link_text();
-        post.capitalize_tags();
-        post.confirm();
-        post.capitalize_tags(); // again because I might have added new tags
-        post.render();
-
-        imap_session
-            .store(format!("{}", imap_message.message), "+FLAGS (\\Deleted)")
-            .unwrap();
+            Err(e) => {
+                eprintln!("Skipping message {uid}: {e}");
+                report.failed(name, e.to_string());
+
+                // A message we can't parse will never succeed, so get it out of
+                // the way. Other errors may well be transient, so leave those
+                // messages in place to be retried next time.
+                if let (Error::Parse(_), Some(quarantine), false) =
+                    (&e, &config.quarantine, config.dry_run)
+                {
+                    match quarantine.quarantine(imap_session, uid, raw, &e) {
+                        Ok(true) => deleted.push(uid),
+                        Ok(false) => (),
+                        Err(qe) => eprintln!("Unable to quarantine message: {qe}"),
+                    }
+                }
+            }
+        }
+        Ok(())
+    });
+
+    // Expunge applies to the selected mailbox, so this has to happen before
+    // we move on to the next one. It also has to happen if something went
+    // wrong partway through, for the messages we'd already dealt with.
+    mailbox::expunge(imap_session, &deleted)?;
+
+    result
+}
+
+/// Selects the mailbox and calls `f` for each message in it that matches the
+/// search criteria, hasn't already been processed, and passes the sender
+/// filter.
+fn for_each_pending<T, F>(
+    imap_session: &mut Session<T>,
+    source: &SourceMailbox,
+    config: &Config,
+    mut f: F,
+) -> Result<()>
+where
+    T: Read + Write,
+    F: FnMut(&mut Session<T>, Uid, &[u8]) -> Result<()>,
+{
+    imap_session.select(&source.name)?;
+
+    // Work by UID rather than sequence number: UIDs don't change when other
+    // messages are moved or deleted.
+    let mut uids: Vec<Uid> = imap_session
+        .uid_search(config.processed_action.search(&config.search))?
+        .into_iter()
+        .collect();
+    uids.sort();
+
+    let count = uids.len();
+    println!("Found {count} messages in {mailbox}", mailbox = source.name);
+
+    for batch in uids.chunks(config.batch_size) {
+        // Use BODY.PEEK so that fetching doesn't mark messages as read.
+        let messages = imap_session.uid_fetch(mailbox::uid_set(batch), "(UID BODY.PEEK[])")?;
+
+        for imap_message in messages.iter() {
+            let uid = match imap_message.uid {
+                Some(uid) => uid,
+                None => {
+                    eprintln!(
+                        "Server did not return a UID for message {}",
+                        imap_message.message
+                    );
+                    continue;
+                }
+            };
+
+            let raw = match imap_message.body() {
+                Some(raw) => raw,
+                None => {
+                    eprintln!("Server did not return a body for message {uid}");
+                    continue;
+                }
+            };
+
+            if let Some(sender_filter) = &config.sender_filter {
+                if let Err(reason) = sender_filter.check(raw) {
+                    println!("Ignoring message {uid}: {reason}");
+                    continue;
+                }
+            }
+
+            f(imap_session, uid, raw)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns a message into a post and files it, unless it needs review and
+/// there's nobody to review it or I decide against it during review.
+fn process_message(
+    raw: &[u8],
+    name: &str,
+    default_tags: &HashSet<String>,
+    config: &Config,
+    dictionary: &mut TagDictionary,
+    report: &mut Report,
+) -> Result<Disposition> {
+    let mut post = prepare_post(raw, default_tags, dictionary)?;
+
+    // In batch mode, only ask about posts that look like they need help.
+    let interactive = if config.interactive && config.batch {
+        let reasons = config
+            .review_rules
+            .iter()
+            .filter(|rule| post.trips(**rule))
+            .map(|rule| rule.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        if reasons.is_empty() {
+            false
+        } else if io::stdin().is_terminal() {
+            println!("\n{title} needs review: {reasons}", title = post.subject());
+            true
+        } else {
+            report.held(name.to_owned(), reasons);
+            return Ok(Disposition::Skip);
+        }
+    } else {
+        config.interactive
+    };
+
+    if interactive {
+        print_post(&post);
+
+        if let Some(rejection) = post.confirm(dictionary) {
+            report.record(name, post.subject(), &rejection);
+            return Ok(rejection);
+        }
+    }
+
+    if config.dry_run {
+        if !interactive {
+            print_post(&post);
+        }
+
+        report.would_file(post.subject(), post.page_path());
+        return Ok(Disposition::Posted(post.page_path()));
     }
 
-    // Be nice to the server and log out.
-    imap_session.logout().unwrap();
+    let disposition = post.render(&config.zola_root, interactive)?;
+
+    report.record(name, post.subject(), &disposition);
+
+    // Learn from the page as it was committed, which may have been edited
+    // during review.
+    if let Disposition::Posted(path) = &disposition {
+        if let Ok(page) = fs::read_to_string(config.zola_root.join("content").join(path)) {
+            dictionary.add_page(&page);
+        }
+    }
+
+    Ok(disposition)
+}
+
+/// Parses a message and fills in whatever we can find out about its link.
+fn prepare_post(
+    raw: &[u8],
+    default_tags: &HashSet<String>,
+    dictionary: &TagDictionary,
+) -> Result<Post> {
+    // Pick apart the important parts of the message.
+    let mut message = message::Message::parse(raw)?;
+    message.tags.extend(default_tags.iter().cloned());
+
+    // Now turn the parsed message into a pending Zola post.
+    let mut post = Post::from(message);
+
+    // None of this is fatal. If we can't find out who wrote a Mastodon post
+    // or read the linked page, the post is just less complete (and held for
+    // review if `enrichment-failed` is one of the review rules).
+    if let Err(e) = post.update_if_mastodon_link() {
+        eprintln!("Warning: {e}");
+    }
+
+    if let Err(e) = post.update_if_unsigned_mastodon_link() {
+        eprintln!("Warning: {e}");
+    }
+
+    if let Err(e) = post.add_link_text() {
+        eprintln!("Warning: {e}");
+    }
+
+    post.capitalize_tags(dictionary);
+    post.suggest_tags(dictionary);
+
+    Ok(post)
+}
+
+fn print_post(post: &Post) {
+    println!(
+        "\n==> content/{path} <==",
+        path = post.page_path().display()
+    );
+    print!("{markdown}", markdown = post.to_markdown());
 }
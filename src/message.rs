@@ -1,9 +1,14 @@
 use std::collections::HashSet;
 
 use chrono::{offset::FixedOffset, DateTime};
-use imap::types::Fetch;
 use lazy_static::lazy_static;
-use regex::Regex;
+use regex::{Captures, Regex};
+
+use crate::{
+    error::{Error, Result},
+    html,
+    mime::Part,
+};
 
 #[derive(Debug)]
 pub struct Message {
@@ -15,113 +20,445 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn from(message: &Fetch) -> Option<Self> {
-        let body = message.body().expect("Message did not have a body");
-        let body = std::str::from_utf8(body)
-            .expect("Message was not valid UTF-8")
-            .to_string();
-
-        let mut date: Option<DateTime<FixedOffset>> = None;
-        let mut subject: Option<String> = None;
-        let mut boundary: Option<String> = None;
-        let mut link: Option<String> = None;
-        let mut text = "".to_owned();
-        let mut tags: HashSet<String> = HashSet::new();
+    /// Parses a raw RFC 822 message, as fetched from the server or read from
+    /// an `.eml` file or mbox archive.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let root = Part::parse(raw);
 
-        let mut lines = body.lines();
+        let date = root
+            .header("Date")
+            .ok_or_else(|| Error::Parse("no Date header found".to_owned()))?;
 
-        loop {
-            if let Some(line) = lines.next() {
-                if line.is_empty() {
-                    break;
-                }
+        let date = DateTime::parse_from_rfc2822(date)
+            .map_err(|e| Error::Parse(format!("unable to parse date {date:#?}: {e}")))?;
 
-                if line.starts_with("Date: ") {
-                    date = Some(DateTime::parse_from_rfc2822(&line[6..]).unwrap());
-                }
+        let mut tags: HashSet<String> = HashSet::new();
 
-                if line.starts_with("Subject: ") {
-                    let (subj, new_tags) = extract_tags(&line[9..]);
+        let subject = root.decoded_header("Subject").unwrap_or_default();
+        let (subject, new_tags) = extract_tags(&subject);
+        tags.extend(new_tags);
 
-                    subject = Some(subj.trim().to_owned());
-                    tags.extend(new_tags);
-                }
+        let mut link: Option<String> = None;
+        let mut text = "".to_owned();
 
-                if line.starts_with(" boundary=") {
-                    boundary = Some(format!("--{}", (&line[10..]).to_owned()));
+        // Prefer the plain text part, but some share sheets only send HTML.
+        let content = match root.find("text/plain").map(|part| part.body_text()) {
+            Some(plain) if !plain.trim().is_empty() => plain,
+            _ => match root.find("text/html") {
+                Some(html) => {
+                    let (html_link, markdown) = html::to_markdown(&html.body_text());
+                    link = html_link;
+                    markdown
                 }
-            } else {
-                break;
-            }
-        }
-
-        let boundary = if let Some(boundary) = boundary {
-            boundary
-        } else {
-            eprintln!("No boundary value found");
-            return None;
+                None => "".to_owned(),
+            },
         };
 
-        loop {
-            let line = if let Some(line) = lines.next() {
-                line
-            } else {
-                break;
-            };
+        // The fence (e.g. "```") of the code block we're in, if any.
+        let mut fence: Option<String> = None;
 
-            if line == boundary {
-                if let Some(content_type) = lines.next() {
-                    if content_type.to_lowercase().trim() == "content-type: text/plain" {
-                        lines.next(); // ignore blank line
-
-                        loop {
-                            if let Some(line) = lines.next() {
-                                if line == boundary {
-                                    text = text.trim().to_owned();
-                                    break;
-                                } else if line.starts_with("https://") && link.is_none() {
-                                    link = Some(line.to_owned());
-                                } else {
-                                    let (line, new_tags) = extract_tags(line);
-
-                                    text += &line;
-                                    text += "/n";
-
-                                    tags.extend(new_tags);
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-                    }
+        for line in content.lines() {
+            if let Some(open) = &fence {
+                // Code is copied as-is.
+                if line.trim() == open {
+                    fence = None;
                 }
+            } else if let Some(open) = code_fence(line) {
+                fence = Some(open);
+            } else if line.starts_with("https://") && link.is_none() {
+                link = Some(line.to_owned());
+                continue;
+            } else if !is_heading(line) {
+                let (line, new_tags) = extract_tags(line);
+
+                text += &line;
+                text += "\n";
+
+                tags.extend(new_tags);
+                continue;
             }
-        }
 
-        if let Some(date) = date {
-            return Some(Self {
-                date,
-                subject: subject.unwrap_or_default(),
-                link,
-                text: text.trim().to_owned(),
-                tags,
-            });
+            text += line;
+            text += "\n";
         }
 
+        Ok(Self {
+            date,
+            subject: subject.trim().to_owned(),
+            link,
+            text: text.trim().to_owned(),
+            tags,
+        })
+    }
+}
+
+// If this line opens a fenced code block, returns its fence.
+fn code_fence(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence: String = trimmed.chars().take_while(|c| *c == marker).collect();
+
+    if fence.len() >= 3 {
+        Some(fence)
+    } else {
         None
     }
 }
 
+// Markdown ATX headings (`## Notes`).
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+
+    (1..=6).contains(&hashes)
+        && line[hashes..]
+            .chars()
+            .next()
+            .map(char::is_whitespace)
+            .unwrap_or(true)
+}
+
+// Pulls hashtags out of a line of text, leaving URLs and inline code alone.
 fn extract_tags(s: &str) -> (String, HashSet<String>) {
     lazy_static! {
-        static ref HASHTAG: Regex = Regex::new(r#"#(\w+)"#).unwrap();
+        static ref NOT_PROSE: Regex = Regex::new(
+            r#"``.*?``|`[^`]*`|<[a-zA-Z][a-zA-Z0-9+.-]*:[^>\s]*>|\]\([^)\s]*\)|\b[a-zA-Z][a-zA-Z0-9+.-]*://[^\s<>]*"#
+        )
+        .unwrap();
     }
 
-    let tags = HASHTAG
-        .captures_iter(s)
-        .map(|item| item[1].to_owned())
-        .collect();
-    let rem = HASHTAG.replace_all(s, "").to_string();
+    let mut rem = String::with_capacity(s.len());
+    let mut tags = HashSet::new();
+    let mut last_end = 0;
+
+    for not_prose in NOT_PROSE.find_iter(s) {
+        let (prose, prose_tags) = extract_prose_tags(&s[last_end..not_prose.start()]);
+        rem += &prose;
+        tags.extend(prose_tags);
+
+        rem += not_prose.as_str();
+        last_end = not_prose.end();
+    }
+
+    let (prose, prose_tags) = extract_prose_tags(&s[last_end..]);
+    rem += &prose;
+    tags.extend(prose_tags);
+
+    (rem, tags)
+}
+
+// Pulls hashtags out of prose. Besides `#word`, tags can contain hyphens,
+// dots and plus signs (`#usb-c`, `#.NET`, `#c++`), and `#[Rust Analyzer]`
+// allows for spaces.
+//
+// A `#` only starts a tag at the start of a word, so URL fragments
+// (`example.com/page#section`) are left alone, as are issue references like
+// `#123`.
+fn extract_prose_tags(s: &str) -> (String, HashSet<String>) {
+    lazy_static! {
+        static ref HASHTAG: Regex =
+            Regex::new(r#"(^|[^\w/&#.:@?=-])#(?:\[([^\[\]#]+)\]|(\.?\w(?:[\w.+-]*[\w+])?))"#)
+                .unwrap();
+    }
+
+    let mut tags = HashSet::new();
+
+    let rem = HASHTAG
+        .replace_all(s, |captures: &Captures| {
+            let tag = match captures.get(2) {
+                Some(words) => words
+                    .as_str()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                None => captures[3].to_owned(),
+            };
+
+            if tag.is_empty() || tag.chars().all(|c| c.is_ascii_digit()) {
+                // Not a tag after all, so leave it in place.
+                return captures[0].to_owned();
+            }
+
+            tags.insert(tag);
+            captures[1].to_owned()
+        })
+        .to_string();
 
     (rem, tags)
 }
+
+/// Formats a tag as a hashtag that `parse_tag_list` (and `extract_tags`)
+/// will read back as the same tag.
+pub(crate) fn format_hashtag(tag: &str) -> String {
+    if tag.contains(char::is_whitespace) {
+        format!("#[{tag}]")
+    } else {
+        format!("#{tag}")
+    }
+}
+
+/// Reads a list of tags as typed at a prompt: hashtags, or bare words, or
+/// `#[Multi Word]` tags, separated by spaces.
+pub(crate) fn parse_tag_list(s: &str) -> Vec<String> {
+    let mut tags = vec![];
+    let mut rest = s.trim_start();
+
+    while !rest.is_empty() {
+        let (tag, remainder) = match rest.strip_prefix("#[").and_then(|r| r.split_once(']')) {
+            Some((words, remainder)) => (
+                words.split_whitespace().collect::<Vec<_>>().join(" "),
+                remainder,
+            ),
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (rest[..end].trim_start_matches('#').to_owned(), &rest[end..])
+            }
+        };
+
+        if !tag.is_empty() {
+            tags.push(tag);
+        }
+
+        rest = remainder.trim_start();
+    }
+
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::DateTime;
+
+    use super::{extract_tags, format_hashtag, parse_tag_list, Message};
+
+    fn tags(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn apple_mail() {
+        let message = Message::parse(include_bytes!("../tests/fixtures/apple-mail.eml")).unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-01T09:14:27-07:00").unwrap()
+        );
+        assert_eq!(message.subject, "Rust 1.70 is out");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://blog.rust-lang.org/2023/06/01/Rust-1.70.0.html")
+        );
+        assert_eq!(
+            message.text,
+            "Sparse registry protocol is now the default, which makes `cargo update` much \
+             faster. \n\nOnceCell and OnceLock are stable too — finally."
+        );
+        assert_eq!(message.tags, tags(&["rust", "cargo"]));
+    }
+
+    #[test]
+    fn apple_mail_attachment() {
+        let message = Message::parse(include_bytes!(
+            "../tests/fixtures/apple-mail-attachment.eml"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-25T14:02:33-07:00").unwrap()
+        );
+        assert_eq!(message.subject, "Slides from my Zola talk");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://speakerdeck.com/scouten/static-sites-with-zola")
+        );
+        assert_eq!(
+            message.text,
+            "Notes are attached for anyone who wants them."
+        );
+        assert_eq!(message.tags, tags(&["zola", "talks"]));
+    }
+
+    #[test]
+    fn gmail_web() {
+        let message = Message::parse(include_bytes!("../tests/fixtures/gmail-web.eml")).unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-05T20:03:11+02:00").unwrap()
+        );
+        assert_eq!(message.subject, "Vision Pro — first look");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://www.theverge.com/2023/6/5/apple-vision-pro")
+        );
+        assert_eq!(message.text, "Apple's headset costs $3,499. That's… a lot.");
+        assert_eq!(message.tags, tags(&["apple", "AR"]));
+    }
+
+    #[test]
+    fn outlook() {
+        let message = Message::parse(include_bytes!("../tests/fixtures/outlook.eml")).unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-13T16:45:01+00:00").unwrap()
+        );
+        assert_eq!(message.subject, "Café culture and remote work");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://www.example.org/2023/06/cafe-culture")
+        );
+        assert_eq!(
+            message.text,
+            "Working from a café isn’t as productive as it looks."
+        );
+        assert_eq!(message.tags, tags(&["work", "RemoteWork"]));
+    }
+
+    #[test]
+    fn ios_share_sheet() {
+        let message =
+            Message::parse(include_bytes!("../tests/fixtures/ios-share-sheet.eml")).unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-17T07:32:48-07:00").unwrap()
+        );
+        assert_eq!(message.subject, "The Quiet Revolution in Zola Themes");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://www.getzola.org/themes/")
+        );
+        assert_eq!(
+            message.text,
+            "The Quiet Revolution in Zola Themes\n\nSent from my iPhone"
+        );
+        assert_eq!(message.tags, tags(&["zola"]));
+    }
+
+    #[test]
+    fn fastmail() {
+        let message = Message::parse(include_bytes!("../tests/fixtures/fastmail.eml")).unwrap();
+
+        assert_eq!(
+            message.date,
+            DateTime::parse_from_rfc3339("2023-06-21T22:05:19+10:00").unwrap()
+        );
+        assert_eq!(message.subject, "Ångström units and the metre 📏");
+        assert_eq!(
+            message.link.as_deref(),
+            Some("https://en.wikipedia.org/wiki/%C3%85ngstr%C3%B6m")
+        );
+        assert_eq!(
+            message.text,
+            "An old unit that still shows up in crystallography."
+        );
+        assert_eq!(message.tags, tags(&["science", "units"]));
+    }
+
+    #[test]
+    fn missing_date() {
+        let raw = b"From: someone@example.com\nSubject: Hello\n\nhttps://example.com/\n";
+
+        assert!(Message::parse(raw).is_err());
+    }
+
+    #[test]
+    fn simple_hashtags() {
+        let (rem, found) = extract_tags("Good stuff #rust #WebAssembly.");
+
+        assert_eq!(rem, "Good stuff  .");
+        assert_eq!(found, tags(&["rust", "WebAssembly"]));
+    }
+
+    #[test]
+    fn extended_hashtags() {
+        let (rem, found) = extract_tags("New #usb-c hub for #.NET and #c++ work (#node.js)");
+
+        assert_eq!(rem, "New  hub for  and  work ()");
+        assert_eq!(found, tags(&["usb-c", ".NET", "c++", "node.js"]));
+    }
+
+    #[test]
+    fn multi_word_hashtags() {
+        let (rem, found) = extract_tags("Trying #[Rust  Analyzer] today #[]");
+
+        assert_eq!(rem, "Trying  today #[]");
+        assert_eq!(found, tags(&["Rust Analyzer"]));
+    }
+
+    #[test]
+    fn not_hashtags() {
+        for s in [
+            "See https://example.com/page#section for details",
+            "Fixed in #123",
+            "Issue#42 and a&#39;b",
+            "C# is fine",
+        ] {
+            let (rem, found) = extract_tags(s);
+
+            assert_eq!(rem, s);
+            assert!(found.is_empty(), "found tags in {s:#?}: {found:?}");
+        }
+    }
+
+    #[test]
+    fn tag_lists() {
+        assert_eq!(
+            parse_tag_list(" #Rust #[Rust Analyzer]  wasm #c++ "),
+            vec!["Rust", "Rust Analyzer", "wasm", "c++"]
+        );
+
+        for tag in ["Rust", "Rust Analyzer", "USB-C"] {
+            assert_eq!(parse_tag_list(&format_hashtag(tag)), vec![tag]);
+        }
+    }
+
+    #[test]
+    fn urls_and_code_are_not_prose() {
+        for s in [
+            "See https://example.com/page#section and http://x.org/#/route",
+            "Autolink <https://example.com/#top> and [a link](https://example.com/#top)",
+            "Use `#[derive(Debug)]` or ``#[cfg(test)] `x` ``",
+        ] {
+            let (rem, found) = extract_tags(s);
+
+            assert_eq!(rem, s);
+            assert!(found.is_empty(), "found tags in {s:#?}: {found:?}");
+        }
+
+        let (rem, found) = extract_tags("`#[test]` is how #rust does it, see https://x.org/#y");
+
+        assert_eq!(rem, "`#[test]` is how  does it, see https://x.org/#y");
+        assert_eq!(found, tags(&["rust"]));
+    }
+
+    #[test]
+    fn headings_and_code_blocks() {
+        let raw = b"From: someone@example.com\nDate: Thu, 1 Jun 2023 09:14:27 -0700\n\
+                    Subject: Code #rust\n\n\
+                    https://example.com/\n\
+                    # Heading\n\
+                    #[Not a heading] tag\n\
+                    ```rust\n\
+                    #[derive(Debug)]\n\
+                    # not a heading either\n\
+                    ```\n\
+                    Done. #code\n";
+
+        let message = Message::parse(raw).unwrap();
+
+        assert_eq!(message.link.as_deref(), Some("https://example.com/"));
+        assert_eq!(
+            message.text,
+            "# Heading\n tag\n```rust\n#[derive(Debug)]\n# not a heading either\n```\nDone."
+        );
+        assert_eq!(message.tags, tags(&["rust", "Not a heading", "code"]));
+    }
+}
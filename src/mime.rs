@@ -0,0 +1,464 @@
+// A small RFC 5322 / MIME parser.
+//
+// This only understands as much of the format as we need to pick apart the
+// messages that get sent to the TMBU mailbox: unfolded headers, content types
+// with (possibly quoted) parameters, arbitrarily nested multipart bodies, and
+// the quoted-printable and base64 transfer encodings, and RFC 2047 encoded
+// words in headers.
+
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use encoding_rs::{Encoding, UTF_8};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+#[derive(Debug)]
+pub(crate) struct Part {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    children: Vec<Part>,
+}
+
+impl Part {
+    pub fn parse(raw: &[u8]) -> Self {
+        let lines = split_lines(raw);
+
+        // Headers end at the first empty line. A message with no empty line
+        // at all is treated as headers only.
+        let header_end = lines
+            .iter()
+            .position(|line| line.is_empty())
+            .unwrap_or(lines.len());
+
+        let headers = parse_headers(&lines[..header_end]);
+        let body_lines = lines.get(header_end + 1..).unwrap_or_default();
+
+        let mut part = Self {
+            headers,
+            body: body_lines.join(&b'\n'),
+            children: vec![],
+        };
+
+        let content_type = part.content_type();
+        if content_type.mime_type.starts_with("multipart/") {
+            if let Some(boundary) = content_type.param("boundary") {
+                part.children = split_multipart(body_lines, boundary)
+                    .into_iter()
+                    .map(|raw| Self::parse(&raw))
+                    .collect();
+            }
+        }
+
+        part
+    }
+
+    /// Returns the (unfolded) value of the first header with the given name.
+    /// Header names are matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Like `header`, but also decodes any RFC 2047 encoded words
+    /// (`=?UTF-8?Q?Caf=C3=A9?=`) in the value.
+    pub fn decoded_header(&self, name: &str) -> Option<String> {
+        self.header(name).map(decode_encoded_words)
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        self.header("Content-Type")
+            .map(ContentType::parse)
+            .unwrap_or_default()
+    }
+
+    pub fn is_attachment(&self) -> bool {
+        self.header("Content-Disposition")
+            .map(|cd| {
+                cd.trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("attachment")
+            })
+            .unwrap_or(false)
+    }
+
+    /// Depth-first search for the first non-attachment part with the given
+    /// MIME type (e.g. `text/plain`). This may return `self` for single-part
+    /// messages.
+    pub fn find(&self, mime_type: &str) -> Option<&Part> {
+        if self.children.is_empty() {
+            if !self.is_attachment() && self.content_type().mime_type == mime_type {
+                return Some(self);
+            }
+            return None;
+        }
+
+        self.children.iter().find_map(|child| child.find(mime_type))
+    }
+
+    /// Returns the body with any `Content-Transfer-Encoding` removed.
+    pub fn decoded_body(&self) -> Vec<u8> {
+        let encoding = self
+            .header("Content-Transfer-Encoding")
+            .map(|cte| cte.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match encoding.as_str() {
+            "quoted-printable" => {
+                quoted_printable::decode(&self.body, quoted_printable::ParseMode::Robust)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Unable to decode quoted-printable body: {e}");
+                        self.body.clone()
+                    })
+            }
+            "base64" => {
+                let encoded: Vec<u8> = self
+                    .body
+                    .iter()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .copied()
+                    .collect();
+
+                BASE64.decode(encoded).unwrap_or_else(|e| {
+                    eprintln!("Unable to decode base64 body: {e}");
+                    self.body.clone()
+                })
+            }
+            _ => self.body.clone(),
+        }
+    }
+
+    /// Returns the decoded body as text, honoring the `charset` parameter of
+    /// the content type. Unknown charsets are treated as UTF-8.
+    pub fn body_text(&self) -> String {
+        let body = self.decoded_body();
+
+        let encoding = self
+            .content_type()
+            .param("charset")
+            .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(UTF_8);
+
+        let (text, _, _) = encoding.decode(&body);
+        text.into_owned()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ContentType {
+    pub(crate) mime_type: String,
+    params: Vec<(String, String)>,
+}
+
+impl ContentType {
+    fn parse(value: &str) -> Self {
+        let mut segments = split_params(value).into_iter();
+
+        let mime_type = segments
+            .next()
+            .map(|s| s.trim().to_ascii_lowercase())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "text/plain".to_owned());
+
+        let params = segments
+            .filter_map(|segment| {
+                let (name, value) = segment.split_once('=')?;
+                Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+            })
+            .collect();
+
+        Self { mime_type, params }
+    }
+
+    /// Returns the value of the named parameter. Parameter names are matched
+    /// case-insensitively.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Default for ContentType {
+    fn default() -> Self {
+        // RFC 2045, section 5.2.
+        Self {
+            mime_type: "text/plain".to_owned(),
+            params: vec![("charset".to_owned(), "us-ascii".to_owned())],
+        }
+    }
+}
+
+// Some senders omit base64 padding, so don't insist on it.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+fn split_lines(raw: &[u8]) -> Vec<&[u8]> {
+    let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+
+    raw.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect()
+}
+
+fn parse_headers(lines: &[&[u8]]) -> Vec<(String, String)> {
+    let mut unfolded: Vec<String> = vec![];
+
+    for line in lines {
+        let line = String::from_utf8_lossy(line);
+
+        if line.starts_with([' ', '\t']) {
+            if let Some(prev) = unfolded.last_mut() {
+                prev.push_str(&line);
+                continue;
+            }
+        }
+
+        unfolded.push(line.into_owned());
+    }
+
+    unfolded
+        .into_iter()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_owned(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+fn split_multipart(lines: &[&[u8]], boundary: &str) -> Vec<Vec<u8>> {
+    let delimiter = format!("--{boundary}");
+    let close_delimiter = format!("--{boundary}--");
+
+    let mut parts: Vec<Vec<u8>> = vec![];
+    let mut current: Option<Vec<&[u8]>> = None;
+
+    for line in lines {
+        // Transport padding (trailing whitespace) is allowed after a
+        // delimiter line.
+        let trimmed = line.trim_ascii_end();
+
+        if trimmed == close_delimiter.as_bytes() {
+            break;
+        }
+
+        if trimmed == delimiter.as_bytes() {
+            if let Some(part) = current.take() {
+                parts.push(part.join(&b'\n'));
+            }
+            current = Some(vec![]);
+            continue;
+        }
+
+        // Anything before the first delimiter is preamble and is ignored.
+        if let Some(part) = current.as_mut() {
+            part.push(line);
+        }
+    }
+
+    if let Some(part) = current.take() {
+        parts.push(part.join(&b'\n'));
+    }
+
+    parts
+}
+
+fn decode_encoded_words(value: &str) -> String {
+    lazy_static! {
+        static ref ENCODED_WORD: Regex =
+            Regex::new(r#"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?="#).unwrap();
+    }
+
+    let mut result = String::with_capacity(value.len());
+    let mut last_end = 0;
+    let mut last_was_encoded = false;
+
+    for captures in ENCODED_WORD.captures_iter(value) {
+        let word = captures.get(0).unwrap();
+        let between = &value[last_end..word.start()];
+
+        // Whitespace between two adjacent encoded words is not displayed
+        // (RFC 2047, section 6.2).
+        if !(last_was_encoded && between.trim().is_empty()) {
+            result.push_str(between);
+        }
+
+        match decode_encoded_word(&captures) {
+            Some(decoded) => {
+                result.push_str(&decoded);
+                last_was_encoded = true;
+            }
+            None => {
+                result.push_str(word.as_str());
+                last_was_encoded = false;
+            }
+        }
+
+        last_end = word.end();
+    }
+
+    result.push_str(&value[last_end..]);
+    result
+}
+
+fn decode_encoded_word(captures: &Captures) -> Option<String> {
+    // RFC 2231 allows a language suffix on the charset (`UTF-8*en`).
+    let charset = captures[1].split('*').next().unwrap_or_default();
+    let encoding = Encoding::for_label(charset.as_bytes())?;
+
+    let text = &captures[3];
+    let bytes = if captures[2].eq_ignore_ascii_case("b") {
+        BASE64.decode(text).ok()?
+    } else {
+        decode_q(text)
+    };
+
+    let (decoded, _, _) = encoding.decode(&bytes);
+    Some(decoded.into_owned())
+}
+
+// The "Q" encoding is quoted-printable, except that underscore stands in for
+// a space. (Encoded as `=20` so that a trailing one isn't dropped as
+// trailing whitespace.)
+fn decode_q(text: &str) -> Vec<u8> {
+    let text = text.replace('_', "=20");
+
+    quoted_printable::decode(&text, quoted_printable::ParseMode::Robust)
+        .unwrap_or_else(|_| text.into_bytes())
+}
+
+// Splits a structured header value on semicolons, ignoring any that appear
+// inside a quoted string.
+fn split_params(value: &str) -> Vec<String> {
+    let mut segments = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for c in value.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' if in_quotes => {
+                current.push(c);
+                escaped = true;
+            }
+            '"' => {
+                current.push(c);
+                in_quotes = !in_quotes;
+            }
+            ';' if !in_quotes => {
+                segments.push(std::mem::take(&mut current));
+            }
+            _ => current.push(c),
+        }
+    }
+
+    segments.push(current);
+    segments
+}
+
+fn unquote(value: &str) -> String {
+    let inner = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return value.to_owned(),
+    };
+
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                result.push(escaped);
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Part;
+
+    #[test]
+    fn folded_headers() {
+        let part = Part::parse(b"Subject: A subject that\r\n  goes on\r\n\tand on\r\n\r\nBody\r\n");
+
+        assert_eq!(
+            part.header("subject"),
+            Some("A subject that  goes on\tand on")
+        );
+        assert_eq!(part.body_text(), "Body");
+    }
+
+    #[test]
+    fn boundary_on_content_type_line() {
+        let part = Part::parse(
+            b"Content-Type: multipart/alternative; boundary=abc\n\n\
+              --abc\nContent-Type: text/html\n\n<p>HTML</p>\n\
+              --abc\nContent-Type: text/plain\n\nPlain\n\
+              --abc--\n",
+        );
+
+        assert_eq!(part.find("text/plain").unwrap().body_text(), "Plain");
+        assert_eq!(part.find("text/html").unwrap().body_text(), "<p>HTML</p>");
+    }
+
+    #[test]
+    fn quoted_boundary() {
+        let part = Part::parse(
+            b"Content-Type: multipart/mixed;\n\tboundary=\"x;y=z\"; charset=utf-8\n\n\
+              --x;y=z\nContent-Type: text/plain\n\nInside\n\
+              --x;y=z--\n",
+        );
+
+        assert_eq!(part.content_type().param("boundary"), Some("x;y=z"));
+        assert_eq!(part.content_type().param("charset"), Some("utf-8"));
+        assert_eq!(part.find("text/plain").unwrap().body_text(), "Inside");
+    }
+
+    #[test]
+    fn adjacent_encoded_words() {
+        let part = Part::parse(
+            b"Subject: =?UTF-8?Q?Caf=C3=A9_?= =?UTF-8?B?Y3VsdHVyZQ==?=\n\t=?utf-8?q?!?= and more\n\n",
+        );
+
+        assert_eq!(
+            part.decoded_header("Subject").as_deref(),
+            Some("Café culture! and more")
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        let part = Part::parse(include_bytes!(
+            "../tests/fixtures/apple-mail-attachment.eml"
+        ));
+
+        // The attachment is `text/plain` too, but isn't the message body.
+        let plain = part.find("text/plain").unwrap();
+        assert!(plain.body_text().starts_with("https://speakerdeck.com/"));
+
+        let attachment = &part.children[1];
+        assert!(attachment.is_attachment());
+        assert_eq!(
+            attachment.body_text(),
+            "Talk notes\nhttps://example.com/not-the-link\n#notes\n"
+        );
+    }
+}
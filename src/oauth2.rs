@@ -0,0 +1,157 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// OAuth2 settings for providers (Gmail, Microsoft 365) that won't accept a
+/// password for IMAP.
+///
+/// We never do the interactive part of the OAuth2 flow. Instead, the refresh
+/// token is obtained once, out of band, and used here to mint access tokens
+/// as needed. Access tokens (and any replacement refresh token the provider
+/// hands back) are cached locally between runs.
+pub(crate) struct OAuth2 {
+    pub(crate) token_url: String,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: Option<String>,
+    pub(crate) refresh_token: String,
+    pub(crate) scope: Option<String>,
+    pub(crate) cache_path: PathBuf,
+}
+
+impl OAuth2 {
+    /// Returns a current access token, refreshing it if necessary.
+    pub fn access_token(&self) -> Result<String> {
+        let cached = self.read_cache();
+
+        if let Some(cached) = &cached {
+            // Leave some slack so the token doesn't expire mid-login.
+            if cached.expires_at > (Utc::now() + Duration::seconds(60)).timestamp() {
+                return Ok(cached.access_token.clone());
+            }
+        }
+
+        // Some providers rotate refresh tokens, in which case the newest one
+        // is in the cache.
+        let refresh_token = cached
+            .and_then(|cached| cached.refresh_token)
+            .unwrap_or_else(|| self.refresh_token.clone());
+
+        let mut params = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+            ("client_id", &self.client_id),
+        ];
+
+        if let Some(client_secret) = &self.client_secret {
+            params.push(("client_secret", client_secret));
+        }
+
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+
+        let response: TokenResponse = reqwest::blocking::Client::new()
+            .post(&self.token_url)
+            .form(&params)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .map_err(|e| Error::network(&self.token_url, e))?;
+
+        let token = CachedToken {
+            configured_refresh_token: Some(self.refresh_token.clone()),
+            access_token: response.access_token,
+            expires_at: (Utc::now() + Duration::seconds(response.expires_in.unwrap_or(3600)))
+                .timestamp(),
+            refresh_token: response.refresh_token.or(Some(refresh_token)),
+        };
+
+        self.write_cache(&token);
+
+        Ok(token.access_token)
+    }
+
+    // A cache left over from a different configured refresh token is stale:
+    // I've probably re-authorized, and whatever it holds has been revoked.
+    fn read_cache(&self) -> Option<CachedToken> {
+        let json = fs::read(&self.cache_path).ok()?;
+        let cached: CachedToken = serde_json::from_slice(&json).ok()?;
+
+        if cached.configured_refresh_token.as_ref() == Some(&self.refresh_token) {
+            Some(cached)
+        } else {
+            None
+        }
+    }
+
+    fn write_cache(&self, token: &CachedToken) {
+        let write = || -> std::io::Result<()> {
+            if let Some(dir) = self.cache_path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            fs::write(&self.cache_path, serde_json::to_vec_pretty(token)?)?;
+
+            // The cache holds credentials, so keep it private.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&self.cache_path, fs::Permissions::from_mode(0o600))?;
+            }
+
+            Ok(())
+        };
+
+        if let Err(e) = write() {
+            eprintln!(
+                "Unable to cache OAuth2 token at {path:#?}: {e}",
+                path = self.cache_path
+            );
+        }
+    }
+}
+
+/// The SASL XOAUTH2 mechanism, as used by Gmail and Microsoft 365.
+pub(crate) struct XOAuth2 {
+    pub(crate) user: String,
+    pub(crate) access_token: String,
+}
+
+impl imap::Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&self, challenge: &[u8]) -> Self::Response {
+        // If authentication fails, the server sends a (non-empty) challenge
+        // describing the error and expects an empty response, after which it
+        // reports the failure.
+        if !challenge.is_empty() {
+            return "".to_owned();
+        }
+
+        format!(
+            "user={user}\x01auth=Bearer {token}\x01\x01",
+            user = self.user,
+            token = self.access_token
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedToken {
+    // The refresh token from the config that this cache started from.
+    #[serde(default)]
+    configured_refresh_token: Option<String>,
+    access_token: String,
+    expires_at: i64, // Unix timestamp
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+}
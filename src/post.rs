@@ -1,14 +1,29 @@
 use std::{
-    collections::HashSet, env, fs, fs::File, io, io::Write, path::PathBuf, process::Command,
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fs,
+    fs::OpenOptions,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
 };
 
 use chrono::{offset::FixedOffset, DateTime, Datelike};
+use deunicode::deunicode;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
-use titlecase::titlecase;
 
-use crate::{message::Message, read_line::ReadLine};
+use crate::{
+    error::{Error, Result},
+    message::{format_hashtag, parse_tag_list, Message},
+    read_line::ReadLine,
+    review::{Choice, Disposition, ReviewRule},
+    suggest::{self, PageTags},
+    tags::{page_title_and_tags, TagDictionary},
+};
 
 #[derive(Debug)]
 #[allow(dead_code)] // TEMPORARY while building
@@ -19,6 +34,9 @@ pub struct Post {
     text: String,
     tags: HashSet<String>,
     via: Option<String>,
+    enrichment_failed: bool,
+    page_tags: PageTags,
+    suggested_tags: Vec<String>,
 }
 
 impl Post {
@@ -30,10 +48,13 @@ impl Post {
             text: message.text,
             tags: message.tags,
             via: None,
+            enrichment_failed: false,
+            page_tags: PageTags::default(),
+            suggested_tags: vec![],
         }
     }
 
-    pub fn update_if_mastodon_link(&mut self) {
+    pub fn update_if_mastodon_link(&mut self) -> Result<()> {
         // If the link is a Mastodon post, read it and
         // update the link and text accordingly.
 
@@ -41,11 +62,13 @@ impl Post {
         let link = match self.link {
             Some(ref link) => link.to_owned(),
             None => {
-                return;
+                return Ok(());
             }
         };
 
-        let client = reqwest::blocking::Client::builder().build().unwrap();
+        let client = reqwest::blocking::Client::builder()
+            .build()
+            .map_err(|e| Error::network(&link, e))?;
 
         let note: MastodonNote = match client
             .get(&link)
@@ -56,7 +79,7 @@ impl Post {
             Ok(resp) => resp,
             Err(e) => {
                 eprintln!("Unable to follow post link {link}\n\n{e}\n");
-                return;
+                return Ok(());
             }
         };
 
@@ -70,7 +93,8 @@ impl Post {
                 {
                     Ok(resp) => resp,
                     Err(e) => {
-                        panic!("Unable to follow user link {link}\n\n{e}\n", link = att);
+                        self.enrichment_failed = true;
+                        return Err(Error::network(att, e));
                     }
                 }
             }
@@ -82,7 +106,8 @@ impl Post {
                         name: "Rust Trending".to_owned(),
                     }
                 } else {
-                    panic!("No known user for link {link}");
+                    self.enrichment_failed = true;
+                    return Err(Error::network(&link, "no known Mastodon user for post"));
                 }
             }
         };
@@ -120,9 +145,11 @@ impl Post {
         )
         .trim()
         .to_owned();
+
+        Ok(())
     }
 
-    pub fn update_if_unsigned_mastodon_link(&mut self) {
+    pub fn update_if_unsigned_mastodon_link(&mut self) -> Result<()> {
         // If the link is a Mastodon post from a server that
         // requires signed requests, read it and update the link
         // and text accordingly.
@@ -131,11 +158,13 @@ impl Post {
         let link = match self.link {
             Some(ref link) => link.to_owned(),
             None => {
-                return;
+                return Ok(());
             }
         };
 
-        let client = reqwest::blocking::Client::builder().build().unwrap();
+        let client = reqwest::blocking::Client::builder()
+            .build()
+            .map_err(|e| Error::network(&link, e))?;
 
         let post = match client
             .get(&link)
@@ -146,19 +175,17 @@ impl Post {
             Ok(resp) => resp,
             Err(e) => {
                 eprintln!("Unable to follow post link as HTML {link}\n\n{e}\n");
-                return;
+                return Ok(());
             }
         };
 
-        dbg!(&post);
-
         let user = if link.starts_with("https://botsin.space/@RustTrending/") {
             MastodonUser {
                 name: "Rust Trending".to_owned(),
             }
         } else {
             eprintln!("Need user for link {link}");
-            return;
+            return Ok(());
         };
 
         // Attempt to pull post content out of meta content header.
@@ -171,7 +198,7 @@ impl Post {
         let mut user_comment = if let Some(content_capture) = META_CONTENT.captures(&post) {
             content_capture[1].to_owned()
         } else {
-            return;
+            return Ok(());
         };
 
         // OK, this is likely a Mastodon post link.
@@ -206,9 +233,11 @@ impl Post {
         )
         .trim()
         .to_owned();
+
+        Ok(())
     }
 
-    pub fn add_link_text(&mut self) {
+    pub fn add_link_text(&mut self) -> Result<()> {
         // If post contains a link, grab its title and
         // add that link to the end of the post message.
 
@@ -216,17 +245,18 @@ impl Post {
         let link = match self.link {
             Some(ref link) => link.to_owned(),
             None => {
-                return;
+                return Ok(());
             }
         };
 
-        let body = match reqwest::blocking::get(&link).and_then(|r| r.text()) {
-            Ok(body) => body,
-            Err(e) => {
-                eprintln!("Unable to follow post link {link}\n\n{e}\n");
-                return;
-            }
-        };
+        let body = reqwest::blocking::get(&link)
+            .and_then(|r| r.text())
+            .map_err(|e| {
+                self.enrichment_failed = true;
+                Error::network(&link, e)
+            })?;
+
+        self.page_tags = PageTags::from_html(&body);
 
         lazy_static! {
             static ref TITLE: Regex = Regex::new(r#"<title>(.*)</title>"#).unwrap();
@@ -248,126 +278,105 @@ impl Post {
         let site_name = site_name.map_or("".to_owned(), |s| format!("{s}: "));
 
         self.text = format!("{text}\n\n{site_name}[{title}]({link})", text = self.text);
+
+        Ok(())
     }
 
-    pub fn capitalize_tags(&mut self) {
+    /// Works out which tags to suggest (but not add) for this post, from the
+    /// linked page and from the tags given to similar posts.
+    pub fn suggest_tags(&mut self, dictionary: &TagDictionary) {
+        // The page's own tags are usually good, but only take a few of any
+        // that would be new to the site. Keywords are only worth taking if
+        // they're tags we already use.
+        let new_page_tags = self
+            .page_tags
+            .tags
+            .iter()
+            .filter(|tag| !dictionary.is_known(tag))
+            .take(3);
+
+        let known_page_tags = self
+            .page_tags
+            .tags
+            .iter()
+            .chain(&self.page_tags.keywords)
+            .filter(|tag| dictionary.is_known(tag));
+
+        let site_name = self
+            .link
+            .as_deref()
+            .and_then(suggest::site_name)
+            .filter(|name| dictionary.is_known(name));
+
+        let model_tags = dictionary.suggest(&format!("{}\n{}", self.subject, self.text));
+
+        let mut suggested: Vec<String> = vec![];
+
+        for tag in known_page_tags
+            .chain(new_page_tags)
+            .cloned()
+            .chain(site_name)
+            .chain(model_tags)
+        {
+            let tag = dictionary.canonical(&tag);
+            if !self.tags.contains(&tag) && !suggested.contains(&tag) {
+                suggested.push(tag);
+            }
+        }
+
+        self.suggested_tags = suggested;
+    }
+
+    /// Returns true if this post meets the condition in the given review
+    /// rule.
+    pub fn trips(&self, rule: ReviewRule) -> bool {
+        match rule {
+            ReviewRule::NoTags => self.tags.is_empty(),
+            ReviewRule::NoLink => self.link.is_none(),
+            ReviewRule::EnrichmentFailed => self.enrichment_failed,
+        }
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn suggested_tags(&self) -> &[String] {
+        &self.suggested_tags
+    }
+
+    pub fn capitalize_tags(&mut self, dictionary: &TagDictionary) {
         self.tags = self
             .tags
             .iter()
-            .map(|tag| {
-                let mut tag = titlecase(tag);
-                match tag.as_str() {
-                    "1password" => {
-                        tag = "1Password".to_owned();
-                    }
-                    "Activitypub" => {
-                        tag = "ActivityPub".to_owned();
-                    }
-                    "Aqi" => {
-                        tag = "AQI".to_owned();
-                    }
-                    "Aws" => {
-                        tag = "AWS".to_owned();
-                    }
-                    "Cicd" => {
-                        tag = "CICD".to_owned();
-                    }
-                    "Cli" => {
-                        tag = "CLI".to_owned();
-                    }
-                    "Crdt" => {
-                        tag = "CRDT".to_owned();
-                    }
-                    "Css" => {
-                        tag = "CSS".to_owned();
-                    }
-                    "Cta" => {
-                        tag = "CTA".to_owned();
-                    }
-                    "Git" => {
-                        tag = "git".to_owned();
-                    }
-                    "Github" => {
-                        tag = "GitHub".to_owned();
-                    }
-                    "Githubactions" => {
-                        tag = "GitHubActions".to_owned();
-                    }
-                    "Html" => {
-                        tag = "HTML".to_owned();
-                    }
-                    "Ios" => {
-                        tag = "iOS".to_owned();
-                    }
-                    "Iphone" => {
-                        tag = "iPhone".to_owned();
-                    }
-                    "Javascript" => {
-                        tag = "JavaScript".to_owned();
-                    }
-                    "Oss" => {
-                        tag = "OSS".to_owned();
-                    }
-                    "Pdf" => {
-                        tag = "PDF".to_owned();
-                    }
-                    "Sast" => {
-                        tag = "SAST".to_owned();
-                    }
-                    "Sbom" => {
-                        tag = "SBOM".to_owned();
-                    }
-                    "Sql" => {
-                        tag = "SQL".to_owned();
-                    }
-                    "Sqlite" => {
-                        tag = "SQLite".to_owned();
-                    }
-                    "Usb" => {
-                        tag = "USB".to_owned();
-                    }
-                    "Usb-C" => {
-                        tag = "USB-C".to_owned();
-                    }
-                    "Vscode" => {
-                        tag = "VSCode".to_owned();
-                    }
-                    "Wasm" => {
-                        tag = "WASM".to_owned();
-                    }
-                    _ => (),
-                };
-                tag
-            })
+            .map(|tag| dictionary.canonical(tag))
             .collect();
     }
 
-    pub fn render(&self) {
-        let zola_path = env::var("TMBU_ZOLA_ROOT").unwrap();
-
+    /// Returns the path for this post relative to the Zola site's `content`
+    /// directory.
+    pub fn page_path(&self) -> PathBuf {
         let date = self.date.date_naive();
 
-        let mut page_path = PathBuf::from(&zola_path);
-        page_path = page_path.join("content");
-        page_path = page_path.join(date.year().to_string());
-        page_path = page_path.join(format!("{month:02}", month = date.month()));
-        fs::create_dir_all(&page_path).unwrap();
-
-        page_path = page_path.join(format!(
-            "{day:02}-{slug}.md",
-            day = date.day(),
-            slug = slug_from_title(&self.subject)
-        ));
+        PathBuf::from(date.year().to_string())
+            .join(format!("{month:02}", month = date.month()))
+            .join(format!(
+                "{day:02}-{slug}.md",
+                day = date.day(),
+                slug = slug_from_title(&self.subject)
+            ))
+    }
 
-        println!("\nCreating blog post at {page_path:#?}");
+    /// Returns the Zola page (front matter and all) for this post.
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
 
-        let mut md = File::create(&page_path).unwrap();
-        writeln!(md, "+++").unwrap();
-        writeln!(md, "title = {title:#?}", title = self.subject).unwrap();
-        writeln!(md, "date = {date:#?}", date = self.date).unwrap();
-        writeln!(md).unwrap();
+        md += "+++\n";
+        md += &format!("title = {title:#?}\n", title = self.subject);
+        md += &format!("date = {date:#?}\n", date = self.date);
+        md += "\n";
 
-        writeln!(md, "[taxonomies]").unwrap();
+        md += "[taxonomies]\n";
 
         if !self.tags.is_empty() {
             let mut tags = self
@@ -378,67 +387,208 @@ impl Post {
 
             tags.sort();
 
-            writeln!(md, "tag = [{tags}]", tags = tags.join(", ")).unwrap();
+            md += &format!("tag = [{tags}]\n", tags = tags.join(", "));
         }
 
         if let Some(ref via) = self.via {
-            writeln!(md, "via = [{via}]", via = format!("{via:#?}")).unwrap();
+            md += &format!("via = [{via:#?}]\n");
         }
-        writeln!(md, "+++").unwrap();
-        writeln!(md).unwrap();
+        md += "+++\n";
+        md += "\n";
 
         let text = format!("{text}\n\n", text = self.text);
         let (before, after) = text.split_once("\n\n").unwrap();
 
-        writeln!(md, "{before}", before = before.trim()).unwrap();
-        writeln!(md).unwrap();
+        md += &format!("{before}\n", before = before.trim());
+        md += "\n";
+
+        md += "<!-- more -->\n";
+        md += "\n";
+
+        md += &format!("{after}\n", after = after.trim());
 
-        writeln!(md, "<!-- more -->").unwrap();
-        writeln!(md).unwrap();
+        md
+    }
+
+    /// Writes the post into the Zola site and commits it. Refuses to
+    /// overwrite a page that's already there.
+    ///
+    /// If `interactive`, a draft of the page is opened in `$VISUAL` (or
+    /// `$EDITOR`) first, and may be rejected at that point. Nothing is
+    /// written to the site unless the draft is accepted, and the accepted
+    /// draft's title and tags replace this post's.
+    pub fn render(&mut self, zola_root: &Path, interactive: bool) -> Result<Disposition> {
+        let mut relative_path = self.page_path();
+        let mut page_path = zola_root.join("content").join(&relative_path);
+
+        if page_path.exists() {
+            return Err(already_exists(&page_path).into());
+        }
 
-        writeln!(md, "{after}", after = after.trim()).unwrap();
+        let mut md = self.to_markdown();
+
+        if interactive {
+            let draft_path = env::temp_dir().join(format!(
+                "tw-{pid}-{name}",
+                pid = std::process::id(),
+                name = relative_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+            ));
+
+            fs::write(&draft_path, &md)?;
+            let review = review_page(&draft_path);
+            let draft = fs::read_to_string(&draft_path);
+            let _ = fs::remove_file(&draft_path);
+
+            if let Some(rejection) = review? {
+                println!("Abandoning {relative_path:#?}");
+                return Ok(rejection);
+            }
 
-        drop(md);
+            md = draft?;
 
-        println!("Confirm page content:");
-        let mut resp = String::new();
-        io::stdin().read_line(&mut resp).unwrap();
+            // `review_page` won't accept a page whose front matter doesn't
+            // parse.
+            let (title, tags) = page_title_and_tags(&md).unwrap();
+            self.subject = title;
+            self.tags = tags.into_iter().collect();
 
-        Command::new("git")
-            .arg("add")
-            .arg(&page_path)
-            .current_dir(&zola_path)
-            .output()
-            .unwrap();
+            // A new title means a new slug.
+            relative_path = self.page_path();
+            page_path = zola_root.join("content").join(&relative_path);
 
-        Command::new("git")
-            .arg("commit")
-            .arg("-m")
-            .arg(&self.subject)
-            .current_dir(&zola_path)
-            .output()
-            .unwrap();
+            if page_path.exists() {
+                return Err(already_exists(&page_path).into());
+            }
+        }
+
+        if let Some(dir) = page_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        println!("\nCreating blog post at {page_path:#?}");
+
+        // `create_new` in case a page appeared while I was editing.
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&page_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(already_exists(&page_path).into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        file.write_all(md.as_bytes())?;
+        drop(file);
+
+        git(zola_root, &[OsStr::new("add"), page_path.as_os_str()])?;
+        git(
+            zola_root,
+            &[
+                OsStr::new("commit"),
+                OsStr::new("-m"),
+                OsStr::new(&self.subject),
+            ],
+        )?;
+
+        Ok(Disposition::Posted(relative_path))
     }
 
-    pub fn confirm(&mut self) {
-        dbg!(&self);
+    /// Asks whether to post this at all and, if so, for any changes to the
+    /// title and tags. Returns the disposition if I decide not to post it.
+    ///
+    /// If I correct the spelling of a tag, offers to save that spelling in
+    /// the tag dictionary.
+    pub fn confirm(&mut self, dictionary: &mut TagDictionary) -> Option<Disposition> {
+        if let Some(rejection) = Choice::ask(false).rejection() {
+            return Some(rejection);
+        }
 
         self.subject = ReadLine::new("Title").default(self.subject.clone()).get();
 
         let mut tags = self
             .tags
             .iter()
-            .map(|tag| format!("#{tag}"))
+            .map(|tag| format_hashtag(tag))
             .collect::<Vec<String>>();
 
         tags.sort();
 
-        let tags = ReadLine::new("Tags").default(tags.join(" ")).get();
+        // Offer the suggested tags too, but keep them apart so it's easy to
+        // see which ones I didn't ask for.
+        if !self.suggested_tags.is_empty() {
+            println!(
+                "Suggested tags: {suggested}",
+                suggested = self
+                    .suggested_tags
+                    .iter()
+                    .map(|tag| format_hashtag(tag))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            );
+        }
+
+        tags.extend(self.suggested_tags.iter().map(|tag| format_hashtag(tag)));
 
-        self.tags = tags
-            .split(" ")
-            .map(|tag| tag.trim_start_matches("#").to_owned())
-            .collect();
+        let mut default_tags = tags.join(" ");
+        print_tag_warnings(&self.tags, dictionary);
+
+        loop {
+            let tags = ReadLine::new("Tags").default(default_tags.clone()).get();
+            let new_tags = self.read_tags(&tags, dictionary);
+
+            // Entering the same tags twice means I've seen the warnings and
+            // meant it.
+            if tags == default_tags || !print_tag_warnings(&new_tags, dictionary) {
+                self.tags = new_tags;
+                break;
+            }
+
+            println!("Enter the same tags again to keep them.");
+            default_tags = tags;
+        }
+
+        None
+    }
+
+    // Turns the tags I typed into the tags for the post. If I corrected the
+    // spelling of one of the post's tags, offers to save the new spelling.
+    fn read_tags(&self, tags: &str, dictionary: &mut TagDictionary) -> HashSet<String> {
+        let mut new_tags = HashSet::new();
+
+        for tag in parse_tag_list(tags) {
+            let tag = tag.as_str();
+
+            let corrected = self
+                .tags
+                .iter()
+                .any(|old| old != tag && old.to_lowercase() == tag.to_lowercase());
+
+            if !corrected {
+                // Might be new, so give it the usual treatment.
+                new_tags.insert(dictionary.canonical(tag));
+                continue;
+            }
+
+            let save = ReadLine::new(&format!("Save spelling {tag:#?} for future posts? (y/n)"))
+                .default("n".to_owned())
+                .get();
+
+            if save.eq_ignore_ascii_case("y") {
+                if let Err(e) = dictionary.save_spelling(tag) {
+                    eprintln!("Unable to save tag spelling: {e}");
+                }
+            }
+
+            new_tags.insert(tag.to_owned());
+        }
+
+        new_tags
     }
 }
 
@@ -455,14 +605,134 @@ struct MastodonUser {
     pub(crate) name: String,
 }
 
+// Zola creates a new taxonomy term for every tag it hasn't seen before, so
+// point out any new tags that look like typos of existing ones. Returns true
+// if there were any.
+fn print_tag_warnings(tags: &HashSet<String>, dictionary: &TagDictionary) -> bool {
+    let mut warned = false;
+
+    for tag in tags {
+        let misses = dictionary.near_misses(tag);
+        if misses.is_empty() {
+            continue;
+        }
+
+        let misses = misses
+            .iter()
+            .map(|miss| format_hashtag(miss))
+            .collect::<Vec<String>>()
+            .join(" or ");
+
+        println!(
+            "Warning: {tag} would be a new tag. Did you mean {misses}?",
+            tag = format_hashtag(tag)
+        );
+        warned = true;
+    }
+
+    warned
+}
+
+// Lets me edit the page until I'm happy with it. Returns the disposition if
+// I'd rather not post it after all.
+fn review_page(page_path: &Path) -> Result<Option<Disposition>> {
+    loop {
+        if let Err(e) = edit(page_path) {
+            eprintln!("Unable to run editor: {e}");
+        }
+
+        let content = fs::read_to_string(page_path)?;
+        println!("\n{content}");
+
+        loop {
+            match Choice::ask(true) {
+                Choice::Accept if page_title_and_tags(&content).is_none() => {
+                    eprintln!("Unable to read the title and tags from the front matter");
+                }
+                Choice::Accept => return Ok(None),
+                Choice::Edit => break,
+                choice => return Ok(choice.rejection()),
+            }
+        }
+    }
+}
+
+fn already_exists(page_path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{page_path:#?} already exists"),
+    )
+}
+
+fn edit(path: &Path) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+
+    // Run the editor through the shell so that settings like `code --wait`
+    // work.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("{editor} exited with {status}")));
+    }
+
+    Ok(())
+}
+
+fn git(zola_root: &Path, args: &[&OsStr]) -> Result<()> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(zola_root)
+        .output()
+        .map_err(|e| Error::Git(format!("could not be run: {e}")))?;
+
+    if !output.status.success() {
+        return Err(Error::Git(format!(
+            "{command} failed: {stderr}",
+            command = args[0].to_string_lossy(),
+            stderr = String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
 fn slug_from_title(title: &str) -> String {
     lazy_static! {
         static ref NON_WORD_CHARS: Regex = Regex::new(r#"\W+"#).unwrap();
         static ref TRAILING_HYPHEN: Regex = Regex::new(r#"-$"#).unwrap();
     }
 
+    // Transliterate first so that "Café" becomes "cafe" rather than losing
+    // the accented character. Only letters and digits, though: deunicode
+    // would otherwise spell out emoji ("📏" becomes "straight ruler").
+    let title = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+    let title = deunicode(title.trim());
     let title = NON_WORD_CHARS.replace_all(&title, "-").to_string();
     let title = TRAILING_HYPHEN.replace(&title, "").to_string();
-    let title = title.to_ascii_lowercase();
-    title
+    title.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slug_from_title;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug_from_title("Café society"), "cafe-society");
+        assert_eq!(
+            slug_from_title("Ångström units and the metre 📏"),
+            "angstrom-units-and-the-metre"
+        );
+        assert_eq!(slug_from_title("Rust 🦀 is fun!"), "rust-is-fun");
+    }
 }
@@ -0,0 +1,109 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use imap::{types::Uid, Session};
+
+use crate::{error::Result, mailbox};
+
+// Marks messages that `ProcessedAction::Seen` has already handled. Unlike
+// `\Seen`, nothing but us sets it.
+const FILED_KEYWORD: &str = "$TmbuFiled";
+
+/// What to do with a message once it has been turned into a post.
+pub(crate) enum ProcessedAction {
+    /// Move the message to another mailbox, tagged with an IMAP keyword
+    /// naming the post it became.
+    Archive(String),
+
+    /// Mark the message as read and leave it where it is. The message is
+    /// also given the private `$TmbuFiled` keyword, and messages with that
+    /// keyword are skipped on later runs. (Messages I've merely read in my
+    /// mail client are not.)
+    Seen,
+
+    /// Delete the message.
+    Delete,
+}
+
+impl ProcessedAction {
+    /// Parses `archive`, `seen`, or `delete`. Archive mode moves messages
+    /// to `archive_mailbox`.
+    pub fn parse(action: &str, archive_mailbox: String) -> Option<Self> {
+        match action.to_ascii_lowercase().as_str() {
+            "archive" => Some(Self::Archive(archive_mailbox)),
+            "seen" => Some(Self::Seen),
+            "delete" => Some(Self::Delete),
+            _ => None,
+        }
+    }
+
+    /// Narrows `search` (IMAP search criteria) so that it doesn't find
+    /// messages already handled by a previous run. This way, the server
+    /// does the filtering and we never download those messages.
+    ///
+    /// Deleted messages are always skipped, since they may be left behind
+    /// when the server can't expunge just the messages we deleted.
+    pub fn search(&self, search: &str) -> String {
+        match self {
+            Self::Seen => format!("{search} UNDELETED UNKEYWORD {FILED_KEYWORD}"),
+            _ => format!("{search} UNDELETED"),
+        }
+    }
+
+    /// Returns true if the message was flagged `\Deleted` and needs to be
+    /// expunged.
+    pub fn apply<T: Read + Write>(
+        &self,
+        imap_session: &mut Session<T>,
+        uid: Uid,
+        post_path: &Path,
+    ) -> Result<bool> {
+        match self {
+            Self::Archive(archive_mailbox) => {
+                // The keyword travels with the message when it's moved, so
+                // we can trace a post back to the e-mail it came from. It's
+                // only a convenience, though, and some servers don't allow
+                // custom keywords. The post is already committed by now, so
+                // the message has to be moved regardless.
+                if let Err(e) = imap_session.uid_store(
+                    uid.to_string(),
+                    format!("+FLAGS ({})", keyword_for(post_path)),
+                ) {
+                    eprintln!("Unable to tag message {uid} with its post: {e}");
+                }
+
+                mailbox::move_message(imap_session, uid, archive_mailbox)
+            }
+            Self::Seen => {
+                imap_session
+                    .uid_store(uid.to_string(), format!("+FLAGS (\\Seen {FILED_KEYWORD})"))?;
+                Ok(false)
+            }
+            Self::Delete => {
+                imap_session.uid_store(uid.to_string(), "+FLAGS (\\Deleted)")?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+// IMAP keywords are atoms, so anything that isn't allowed in an atom
+// (RFC 3501, section 9) is replaced.
+fn keyword_for(post_path: &Path) -> String {
+    let path = post_path.to_string_lossy();
+
+    let path: String = path
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && !"(){%*\"\\]".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("tmbu:{path}")
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use chrono::Utc;
+use imap::{types::Uid, Session};
+
+use crate::{
+    error::{Error, Result},
+    mailbox,
+};
+
+/// Where to put messages that we can't parse.
+///
+/// The message is moved to an IMAP mailbox so that it no longer blocks the
+/// run, and a local copy with an `X-Tmbu-Error` header describing the failure
+/// is written so there's a record of why it was set aside.
+pub(crate) struct Quarantine {
+    mailbox: String,
+    dir: PathBuf,
+}
+
+impl Quarantine {
+    /// Failed messages are moved to `mailbox` (e.g. `TMBU/Failed`) and
+    /// local copies are written to `dir`.
+    pub fn new(mailbox: String, dir: PathBuf) -> Self {
+        Self { mailbox, dir }
+    }
+
+    pub fn quarantine<T: Read + Write>(
+        &self,
+        imap_session: &mut Session<T>,
+        uid: Uid,
+        raw: &[u8],
+        error: &Error,
+    ) -> Result<bool> {
+        self.record(uid, raw, error)?;
+
+        let deleted = mailbox::move_message(imap_session, uid, &self.mailbox)?;
+
+        println!("Moved message {uid} to {mailbox}", mailbox = self.mailbox);
+
+        Ok(deleted)
+    }
+
+    fn record(&self, uid: Uid, raw: &[u8], error: &Error) -> Result<()> {
+        let path = self.dir.join(format!(
+            "{timestamp}-{uid}.eml",
+            timestamp = Utc::now().format("%Y%m%dT%H%M%S"),
+        ));
+
+        // Header values can't span lines without folding, so keep the reason
+        // on one line.
+        let reason = error.to_string().replace(['\r', '\n'], " ");
+
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(&self.dir)?;
+
+            let mut eml = fs::File::create(&path)?;
+            write!(eml, "X-Tmbu-Error: {reason}\r\n")?;
+            eml.write_all(raw)
+        };
+
+        write().map_err(|source| Error::Write {
+            path: path.clone(),
+            source,
+        })?;
+
+        eprintln!("Saved failed message to {path:#?}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+// Keeps track of what happened to each message during a run so that it can
+// be summarized at the end. This is mostly for batch runs, where nobody is
+// watching the output go by.
+
+use std::{fmt, path::PathBuf};
+
+use crate::review::Disposition;
+
+#[derive(Default)]
+pub(crate) struct Report {
+    filed: Vec<(String, PathBuf)>,
+    would_file: Vec<(String, PathBuf)>,
+    held: Vec<(String, String)>,
+    discarded: Vec<String>,
+    deferred: Vec<String>,
+    failed: Vec<(String, String)>,
+}
+
+impl Report {
+    /// Records what became of a message after review.
+    pub fn record(&mut self, message: &str, title: &str, disposition: &Disposition) {
+        match disposition {
+            Disposition::Posted(path) => self.filed.push((title.to_owned(), path.clone())),
+            Disposition::Skip => self.held(message.to_owned(), "skipped".to_owned()),
+            Disposition::Discard => self.discarded.push(message.to_owned()),
+            Disposition::Defer => self.deferred.push(message.to_owned()),
+        }
+    }
+
+    /// Records a post that a dry run would have filed.
+    pub fn would_file(&mut self, title: &str, path: PathBuf) {
+        self.would_file.push((title.to_owned(), path));
+    }
+
+    /// Records a message that was left alone, either because it needs review
+    /// and nobody is available to review it or because I skipped it.
+    pub fn held(&mut self, message: String, reason: String) {
+        self.held.push((message, reason));
+    }
+
+    /// Records a message that couldn't be turned into a post.
+    pub fn failed(&mut self, message: String, error: String) {
+        self.failed.push((message, error));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filed.is_empty()
+            && self.would_file.is_empty()
+            && self.held.is_empty()
+            && self.discarded.is_empty()
+            && self.deferred.is_empty()
+            && self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Summary: {filed} filed", filed = self.filed.len())?;
+
+        // Only dry runs have anything in this bucket.
+        if !self.would_file.is_empty() {
+            write!(f, ", {count} would file", count = self.would_file.len())?;
+        }
+
+        writeln!(
+            f,
+            ", {held} held, {discarded} discarded, {deferred} deferred, {failed} failed",
+            held = self.held.len(),
+            discarded = self.discarded.len(),
+            deferred = self.deferred.len(),
+            failed = self.failed.len()
+        )?;
+
+        for (title, path) in &self.filed {
+            writeln!(f, "  filed      {title} ({path})", path = path.display())?;
+        }
+
+        for (title, path) in &self.would_file {
+            writeln!(f, "  would file {title} ({path})", path = path.display())?;
+        }
+
+        for (message, reason) in &self.held {
+            writeln!(f, "  held       {message}: {reason}")?;
+        }
+
+        for message in &self.discarded {
+            writeln!(f, "  discarded  {message}")?;
+        }
+
+        for message in &self.deferred {
+            writeln!(f, "  deferred   {message}")?;
+        }
+
+        for (message, error) in &self.failed {
+            writeln!(f, "  failed     {message}: {error}")?;
+        }
+
+        Ok(())
+    }
+}
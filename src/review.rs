@@ -0,0 +1,109 @@
+// Deciding which posts still need a human to look at them, and what that
+// human decided.
+
+use std::{fmt, path::PathBuf};
+
+use crate::read_line::ReadLine;
+
+/// A condition that sends a post to interactive review in batch mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ReviewRule {
+    /// The post has no tags.
+    NoTags,
+
+    /// The message didn't contain a link.
+    NoLink,
+
+    /// The linked page (or the author of a linked Mastodon post) couldn't be
+    /// fetched, so the post is missing details.
+    EnrichmentFailed,
+}
+
+impl ReviewRule {
+    pub fn parse(rule: &str) -> Option<Self> {
+        match rule.trim() {
+            "no-tags" => Some(Self::NoTags),
+            "no-link" => Some(Self::NoLink),
+            "enrichment-failed" => Some(Self::EnrichmentFailed),
+            _ => None,
+        }
+    }
+
+    pub fn all() -> Vec<Self> {
+        vec![Self::NoTags, Self::NoLink, Self::EnrichmentFailed]
+    }
+}
+
+impl fmt::Display for ReviewRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoTags => "no tags",
+            Self::NoLink => "no link",
+            Self::EnrichmentFailed => "unable to fetch link details",
+        })
+    }
+}
+
+/// What became of a message once it was reviewed.
+pub(crate) enum Disposition {
+    /// Posted as the page at this path (relative to the site's `content`
+    /// directory).
+    Posted(PathBuf),
+
+    /// Leave the message where it is, to be looked at again next time.
+    Skip,
+
+    /// Delete the message without posting it.
+    Discard,
+
+    /// Flag the message and move it to the "later" mailbox.
+    Defer,
+}
+
+/// An answer to "what should we do with this post?"
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Choice {
+    Accept,
+    Edit,
+    Skip,
+    Discard,
+    Defer,
+}
+
+impl Choice {
+    /// Asks until it gets a usable answer. Editing is only offered if
+    /// `allow_edit` is true.
+    pub fn ask(allow_edit: bool) -> Self {
+        let prompt = if allow_edit {
+            "(a)ccept, (e)dit again, (s)kip, (d)iscard, or de(f)er"
+        } else {
+            "(a)ccept, (s)kip, (d)iscard, or de(f)er"
+        };
+
+        loop {
+            let answer = ReadLine::new(prompt).default("a".to_owned()).get();
+
+            let choice = match answer.to_ascii_lowercase().as_str() {
+                "a" | "accept" => Self::Accept,
+                "e" | "edit" if allow_edit => Self::Edit,
+                "s" | "skip" => Self::Skip,
+                "d" | "discard" => Self::Discard,
+                "f" | "defer" => Self::Defer,
+                _ => continue,
+            };
+
+            return choice;
+        }
+    }
+
+    /// Returns the disposition for a choice not to post the message, or
+    /// `None` if this choice means carrying on.
+    pub fn rejection(self) -> Option<Disposition> {
+        match self {
+            Self::Accept | Self::Edit => None,
+            Self::Skip => Some(Disposition::Skip),
+            Self::Discard => Some(Disposition::Discard),
+            Self::Defer => Some(Disposition::Defer),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Tag suggestions, for when I don't think to add a hashtag.
+//
+// Suggestions come from two places: what the linked page says about itself
+// (`<meta name="keywords">`, OpenGraph `article:tag`, GitHub repository
+// topics), and which tags earlier posts with similar words were given.
+
+use std::collections::{HashMap, HashSet};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Tags that a fetched page proposes for itself.
+#[derive(Debug, Default)]
+pub(crate) struct PageTags {
+    /// `article:tag` properties and GitHub topics, which are usually chosen
+    /// with some care.
+    pub(crate) tags: Vec<String>,
+
+    /// `<meta name="keywords">`, which is often stuffed with junk.
+    pub(crate) keywords: Vec<String>,
+}
+
+impl PageTags {
+    pub fn from_html(html: &str) -> Self {
+        lazy_static! {
+            static ref META: Regex = Regex::new(r#"(?is)<meta\b[^>]*>"#).unwrap();
+            static ref ATTR: Regex =
+                Regex::new(r#"(?is)\b(name|property|content)\s*=\s*("[^"]*"|'[^']*')"#).unwrap();
+            static ref TOPIC: Regex = Regex::new(
+                r#"(?is)<a\b[^>]*\bclass\s*=\s*"[^"]*\btopic-tag\b[^"]*"[^>]*>\s*([^<]+?)\s*</a>"#
+            )
+            .unwrap();
+        }
+
+        let mut page_tags = Self::default();
+
+        for meta in META.find_iter(html) {
+            let mut name = String::new();
+            let mut content = String::new();
+
+            for attr in ATTR.captures_iter(meta.as_str()) {
+                let value = attr[2][1..attr[2].len() - 1].trim().to_owned();
+                if attr[1].eq_ignore_ascii_case("content") {
+                    content = value;
+                } else {
+                    name = value.to_ascii_lowercase();
+                }
+            }
+
+            match name.as_str() {
+                "keywords" => page_tags.keywords.extend(
+                    content
+                        .split(',')
+                        .map(|keyword| keyword.trim().to_owned())
+                        .filter(|keyword| !keyword.is_empty()),
+                ),
+                "article:tag" if !content.is_empty() => page_tags.tags.push(content),
+                _ => (),
+            }
+        }
+
+        page_tags.tags.extend(
+            TOPIC
+                .captures_iter(html)
+                .map(|topic| topic[1].trim().to_owned()),
+        );
+
+        page_tags
+    }
+}
+
+/// Returns the part of a link's host name that's most likely to be the name
+/// of the site (`github` for `https://github.com/...`).
+pub(crate) fn site_name(link: &str) -> Option<String> {
+    let host = link.split_once("://")?.1;
+    let host = host.split(['/', '?', '#', ':']).next()?;
+
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() < 2 {
+        return None;
+    }
+
+    Some(labels[labels.len() - 2].to_lowercase())
+}
+
+/// Associations between the words in earlier posts and the tags they were
+/// given.
+#[derive(Default)]
+pub(crate) struct TagModel {
+    // Number of posts each word appears in.
+    word_posts: HashMap<String, usize>,
+
+    // For each word, the number of posts it appears in that have each tag.
+    word_tags: HashMap<String, HashMap<String, usize>>,
+}
+
+// How many of the best-scoring tags to suggest, and how much evidence we need
+// for each one.
+const MAX_SUGGESTIONS: usize = 3;
+const MIN_SCORE: f64 = 1.0;
+
+impl TagModel {
+    pub fn learn<S: AsRef<str>>(&mut self, text: &str, tags: &[S]) {
+        for word in words(text) {
+            *self.word_posts.entry(word.clone()).or_default() += 1;
+
+            let word_tags = self.word_tags.entry(word).or_default();
+            for tag in tags {
+                *word_tags.entry(tag.as_ref().to_owned()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Returns the tags that earlier posts using the same words were most
+    /// often given.
+    pub fn suggest(&self, text: &str) -> Vec<String> {
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+
+        for word in words(text) {
+            // A word that has only been seen once says more about that one
+            // post than about the tag.
+            let posts = match self.word_posts.get(&word) {
+                Some(posts) if *posts >= 2 => *posts as f64,
+                _ => continue,
+            };
+
+            for (tag, count) in &self.word_tags[&word] {
+                *scores.entry(tag).or_default() += *count as f64 / posts;
+            }
+        }
+
+        let mut scores: Vec<(&str, f64)> = scores
+            .into_iter()
+            .filter(|(_, score)| *score >= MIN_SCORE)
+            .collect();
+
+        scores.sort_by(|(a, a_score), (b, b_score)| b_score.total_cmp(a_score).then(a.cmp(b)));
+
+        scores
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(tag, _)| tag.to_owned())
+            .collect()
+    }
+}
+
+// The distinct, interesting words in some text.
+fn words(text: &str) -> HashSet<String> {
+    lazy_static! {
+        static ref WORD: Regex = Regex::new(r#"\w+"#).unwrap();
+    }
+
+    const STOP_WORDS: &[&str] = &[
+        "about", "also", "been", "from", "have", "here", "html", "http", "https", "into", "just",
+        "like", "more", "only", "some", "than", "that", "their", "them", "then", "there", "they",
+        "this", "what", "when", "which", "will", "with", "would", "your",
+    ];
+
+    WORD.find_iter(text)
+        .map(|word| word.as_str().to_lowercase())
+        .filter(|word| word.chars().count() >= 4 && !word.chars().all(|c| c.is_ascii_digit()))
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{site_name, PageTags, TagModel};
+
+    #[test]
+    fn page_tags() {
+        let html = r#"<html><head>
+            <meta name="keywords" content="rust, programming,  , web">
+            <meta property="article:tag" content="WebAssembly" />
+            <meta content='Rust' property='article:tag'>
+            <meta name="description" content="Not tags">
+            </head><body>
+            <a class="topic-tag topic-tag-link" href="/topics/zola" title="Topic: zola">
+              zola
+            </a>
+            </body></html>"#;
+
+        let page_tags = PageTags::from_html(html);
+
+        assert_eq!(page_tags.keywords, vec!["rust", "programming", "web"]);
+        assert_eq!(page_tags.tags, vec!["WebAssembly", "Rust", "zola"]);
+    }
+
+    #[test]
+    fn site_names() {
+        assert_eq!(
+            site_name("https://github.com/scouten/tmbu-worker").as_deref(),
+            Some("github")
+        );
+        assert_eq!(
+            site_name("https://blog.rust-lang.org/2023/06/01/Rust-1.70.0.html").as_deref(),
+            Some("rust-lang")
+        );
+        assert_eq!(site_name("https://localhost/"), None);
+        assert_eq!(site_name("not a link"), None);
+    }
+
+    #[test]
+    fn model() {
+        let mut model = TagModel::default();
+        model.learn("Cargo makes building crates easy", &["Rust"]);
+        model.learn("The borrow checker and cargo", &["Rust"]);
+        model.learn("Lenses, cameras and film", &["Photography"]);
+        model.learn("Another film camera review", &["Photography"]);
+
+        assert_eq!(model.suggest("A new cargo feature"), vec!["Rust"]);
+        assert_eq!(
+            model.suggest("Shooting film this weekend"),
+            vec!["Photography"]
+        );
+        assert!(model.suggest("Something else entirely").is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+// What we know about tags: canonical spellings (`AWS`, `iOS`, `git`, ...),
+// aliases (`rustlang` is just `Rust`), and the tags already used in the site
+// (and with what sort of posts).
+//
+// The spellings and aliases live in a TOML file in the Zola site (`tags.toml`
+// unless configured otherwise) so that a new acronym doesn't need a code
+// change. If there is no such file, we start from `default_tags.toml`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use titlecase::titlecase;
+
+use crate::{
+    error::{Error, Result},
+    suggest::TagModel,
+};
+
+const DEFAULT_TAGS: &str = include_str!("default_tags.toml");
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct DictionaryFile {
+    spellings: Vec<String>,
+    aliases: BTreeMap<String, String>,
+}
+
+pub(crate) struct TagDictionary {
+    path: PathBuf,
+    file: DictionaryFile,
+
+    // Lower-cased tag to canonical spelling.
+    spellings: HashMap<String, String>,
+
+    // Lower-cased alias to the tag it stands for.
+    aliases: HashMap<String, String>,
+
+    // Lower-cased tag to the spellings of it used in existing posts, with
+    // how many posts use each one.
+    existing: HashMap<String, HashMap<String, usize>>,
+
+    model: TagModel,
+}
+
+impl TagDictionary {
+    /// Reads the dictionary at `path`, or the shipped default list if there
+    /// is no file there yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let toml = match fs::read_to_string(path) {
+            Ok(toml) => toml,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DEFAULT_TAGS.to_owned(),
+            Err(e) => {
+                return Err(Error::Config(vec![format!(
+                    "unable to read {path:#?}: {e}"
+                )]));
+            }
+        };
+
+        let file: DictionaryFile =
+            toml::from_str(&toml).map_err(|e| Error::Config(vec![format!("{path:#?}: {e}")]))?;
+
+        let mut dictionary = Self {
+            path: path.to_path_buf(),
+            file,
+            spellings: HashMap::new(),
+            aliases: HashMap::new(),
+            existing: HashMap::new(),
+            model: TagModel::default(),
+        };
+
+        dictionary.index();
+        Ok(dictionary)
+    }
+
+    /// Learns the tags used by the existing posts in a Zola `content`
+    /// directory.
+    pub fn scan_site(&mut self, content_dir: &Path) {
+        let mut pages = vec![];
+        find_pages(content_dir, &mut pages);
+
+        for page in pages {
+            match fs::read_to_string(&page) {
+                Ok(page) => self.add_page(&page),
+                Err(e) => eprintln!("Unable to read {page:#?}: {e}"),
+            }
+        }
+    }
+
+    /// Learns the tags used by a Zola page (one from the site, or a post that
+    /// has just been filed so that later posts in the same run see its tags).
+    pub fn add_page(&mut self, page: &str) {
+        if let Some((tags, text)) = parse_page(page) {
+            self.model.learn(&text, &tags);
+            self.add_existing(tags);
+        }
+    }
+
+    fn add_existing<I, S>(&mut self, tags: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for tag in tags {
+            let tag = tag.as_ref();
+
+            *self
+                .existing
+                .entry(tag.to_lowercase())
+                .or_default()
+                .entry(tag.to_owned())
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the canonical spelling of a tag, resolving any alias. In order
+    /// of preference, that's the dictionary spelling, the most common spelling
+    /// in existing posts, or the tag in title case.
+    pub fn canonical(&self, tag: &str) -> String {
+        let tag = self
+            .aliases
+            .get(&tag.to_lowercase())
+            .map(String::as_str)
+            .unwrap_or(tag);
+
+        let key = tag.to_lowercase();
+
+        if let Some(spelling) = self.spellings.get(&key) {
+            return spelling.clone();
+        }
+
+        if let Some(spelling) = self.existing_spelling(&key) {
+            return spelling.to_owned();
+        }
+
+        titlecase(tag)
+    }
+
+    /// Returns true if `tag` is in the dictionary or used by an existing post.
+    pub fn is_known(&self, tag: &str) -> bool {
+        let key = tag.to_lowercase();
+
+        self.spellings.contains_key(&key)
+            || self.aliases.contains_key(&key)
+            || self.existing.contains_key(&key)
+    }
+
+    /// Returns the tags most often given to existing posts with words in
+    /// common with `text`.
+    pub fn suggest(&self, text: &str) -> Vec<String> {
+        self.model.suggest(text)
+    }
+
+    /// If `tag` isn't used by any existing post, returns the existing tags
+    /// that are close enough to it that `tag` might be a typo.
+    pub fn near_misses(&self, tag: &str) -> Vec<String> {
+        let key = tag.to_lowercase();
+
+        if self.existing.contains_key(&key) {
+            return vec![];
+        }
+
+        // Allow one edit for every three characters, up to two, so that short
+        // tags like `AI` and `AR` aren't confused with each other.
+        let len = key.chars().count();
+
+        let mut misses: Vec<String> = self
+            .existing
+            .keys()
+            .filter(|existing| {
+                let distance = strsim::osa_distance(&key, existing);
+                distance <= 2 && distance * 3 <= len
+            })
+            .filter_map(|existing| self.existing_spelling(existing).map(str::to_owned))
+            .collect();
+
+        misses.sort();
+        misses
+    }
+
+    /// Adds (or replaces) the canonical spelling for a tag and writes the
+    /// dictionary back out.
+    pub fn save_spelling(&mut self, spelling: &str) -> Result<()> {
+        let key = spelling.to_lowercase();
+
+        self.file
+            .spellings
+            .retain(|existing| existing.to_lowercase() != key);
+        self.file.spellings.push(spelling.to_owned());
+        self.file
+            .spellings
+            .sort_by_key(|spelling| spelling.to_lowercase());
+
+        self.index();
+
+        let toml = toml::to_string_pretty(&self.file)
+            .map_err(|e| Error::Config(vec![format!("unable to write {:#?}: {e}", self.path)]))?;
+
+        fs::write(&self.path, toml).map_err(|source| Error::Write {
+            path: self.path.clone(),
+            source,
+        })?;
+        Ok(())
+    }
+
+    fn existing_spelling(&self, key: &str) -> Option<&str> {
+        self.existing
+            .get(key)?
+            .iter()
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+            .map(|(spelling, _)| spelling.as_str())
+    }
+
+    fn index(&mut self) {
+        self.spellings = self
+            .file
+            .spellings
+            .iter()
+            .map(|spelling| (spelling.to_lowercase(), spelling.clone()))
+            .collect();
+
+        self.aliases = self
+            .file
+            .aliases
+            .iter()
+            .map(|(alias, tag)| (alias.to_lowercase(), tag.clone()))
+            .collect();
+    }
+}
+
+fn find_pages(dir: &Path, pages: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Unable to read {dir:#?}: {e}");
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            find_pages(&path, pages);
+        } else if path.extension().map(|ext| ext == "md").unwrap_or(false) {
+            pages.push(path);
+        }
+    }
+}
+
+// Pulls `taxonomies.tag` out of a page's TOML front matter, along with the
+// page's text (including its title).
+fn parse_page(page: &str) -> Option<(Vec<String>, String)> {
+    let (front_matter, body) = split_page(page)?;
+
+    let tags = front_matter
+        .get("taxonomies")?
+        .get("tag")?
+        .as_array()?
+        .iter()
+        .filter_map(|tag| tag.as_str().map(str::to_owned))
+        .collect();
+
+    let title = front_matter
+        .get("title")
+        .and_then(|title| title.as_str())
+        .unwrap_or_default();
+
+    Some((tags, format!("{title}\n{body}")))
+}
+
+/// Returns the title and tags from a page's front matter. A page without
+/// tags has an empty list.
+pub(crate) fn page_title_and_tags(page: &str) -> Option<(String, Vec<String>)> {
+    let (front_matter, _) = split_page(page)?;
+
+    let title = front_matter.get("title")?.as_str()?.to_owned();
+    let tags = parse_page(page).map(|(tags, _)| tags).unwrap_or_default();
+
+    Some((title, tags))
+}
+
+fn split_page(page: &str) -> Option<(toml::Table, &str)> {
+    let front_matter = page.strip_prefix("+++")?;
+    let (front_matter, body) = front_matter.split_once("\n+++")?;
+
+    Some((toml::from_str(front_matter).ok()?, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{page_title_and_tags, parse_page, TagDictionary};
+
+    fn default_dictionary() -> TagDictionary {
+        TagDictionary::load(Path::new("/nonexistent/tags.toml")).unwrap()
+    }
+
+    #[test]
+    fn default_spellings() {
+        let dictionary = default_dictionary();
+
+        assert_eq!(dictionary.canonical("aws"), "AWS");
+        assert_eq!(dictionary.canonical("Ios"), "iOS");
+        assert_eq!(dictionary.canonical("git"), "git");
+        assert_eq!(dictionary.canonical("usb-c"), "USB-C");
+        assert_eq!(dictionary.canonical(".net"), ".NET");
+        assert_eq!(dictionary.canonical("rust"), "Rust");
+        assert_eq!(dictionary.canonical("rust analyzer"), "Rust Analyzer");
+    }
+
+    #[test]
+    fn aliases() {
+        let dictionary = default_dictionary();
+
+        assert_eq!(dictionary.canonical("rustlang"), "Rust");
+        assert_eq!(dictionary.canonical("JS"), "JavaScript");
+    }
+
+    #[test]
+    fn existing_spellings() {
+        let mut dictionary = default_dictionary();
+        dictionary.add_existing(["macOS", "macOS", "MacOS"]);
+
+        assert_eq!(dictionary.canonical("macos"), "macOS");
+
+        // The dictionary still wins over existing posts.
+        dictionary.add_existing(["Aws"]);
+        assert_eq!(dictionary.canonical("aws"), "AWS");
+    }
+
+    #[test]
+    fn near_misses() {
+        let mut dictionary = default_dictionary();
+        dictionary.add_existing(["Rust", "Python", "AI"]);
+
+        assert_eq!(dictionary.near_misses("Rustt"), vec!["Rust"]);
+        assert_eq!(dictionary.near_misses("Pyhton"), vec!["Python"]);
+        assert!(dictionary.near_misses("Rust").is_empty());
+        assert!(dictionary.near_misses("AR").is_empty());
+        assert!(dictionary.near_misses("Photography").is_empty());
+    }
+
+    #[test]
+    fn save_spelling() {
+        let dir = std::env::temp_dir().join(format!("tw-tags-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tags.toml");
+
+        let mut dictionary = TagDictionary::load(&path).unwrap();
+        assert_eq!(dictionary.canonical("graphql"), "Graphql");
+
+        dictionary.save_spelling("GraphQL").unwrap();
+        assert_eq!(dictionary.canonical("graphql"), "GraphQL");
+
+        // The saved file has the defaults as well as the new spelling.
+        let dictionary = TagDictionary::load(&path).unwrap();
+        assert_eq!(dictionary.canonical("graphql"), "GraphQL");
+        assert_eq!(dictionary.canonical("aws"), "AWS");
+        assert_eq!(dictionary.canonical("rustlang"), "Rust");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn front_matter() {
+        let page = "+++\ntitle = \"Hello\"\ndate = 2023-06-01T09:14:27-07:00\n\n[taxonomies]\n\
+                    tag = [\"Rust\", \"WASM\"]\nvia = [\"Someone\"]\n+++\n\nText.\n";
+
+        let (tags, text) = parse_page(page).unwrap();
+
+        assert_eq!(tags, vec!["Rust", "WASM"]);
+        assert_eq!(text, "Hello\n\n\nText.\n");
+        assert!(parse_page("No front matter here.").is_none());
+
+        assert_eq!(
+            page_title_and_tags(page).unwrap(),
+            (
+                "Hello".to_owned(),
+                vec!["Rust".to_owned(), "WASM".to_owned()]
+            )
+        );
+        assert_eq!(
+            page_title_and_tags("+++\ntitle = \"Untagged\"\n\n[taxonomies]\n+++\n\nText.\n")
+                .unwrap(),
+            ("Untagged".to_owned(), vec![])
+        );
+    }
+}